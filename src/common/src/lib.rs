@@ -349,6 +349,85 @@ impl From<u64> for GasPrice {
     }
 }
 
+/// A 256-bit unsigned integer, as emitted by Cairo contracts in a `Uint256` struct which splits
+/// the value into a low and a high 128-bit limb, each stored in its own [Felt].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct U256([u8; 32]);
+
+#[derive(Debug, thiserror::Error)]
+#[error("Uint256 limb has more than 128 bits")]
+pub struct LimbOverflowError;
+
+impl U256 {
+    pub const ZERO: U256 = U256([0u8; 32]);
+
+    /// Combines the `low` and `high` limbs of a Cairo `Uint256`.
+    pub fn from_limbs(low: Felt, high: Felt) -> Result<Self, LimbOverflowError> {
+        let low = low.as_be_bytes();
+        let high = high.as_be_bytes();
+        if low[..16].iter().chain(&high[..16]).any(|b| *b != 0) {
+            return Err(LimbOverflowError);
+        }
+
+        let mut buf = [0u8; 32];
+        buf[..16].copy_from_slice(&high[16..]);
+        buf[16..].copy_from_slice(&low[16..]);
+
+        Ok(Self(buf))
+    }
+
+    /// Returns the low 128-bit limb.
+    pub fn low(&self) -> u128 {
+        u128::from_be_bytes(self.0[16..].try_into().expect("slice is 16 bytes long"))
+    }
+
+    /// Returns the high 128-bit limb.
+    pub fn high(&self) -> u128 {
+        u128::from_be_bytes(self.0[..16].try_into().expect("slice is 16 bytes long"))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self == &Self::ZERO
+    }
+
+    /// Returns the big-endian representation of this [U256].
+    pub const fn to_be_bytes(self) -> [u8; 32] {
+        self.0
+    }
+
+    /// Returns a reference to the big-endian representation of this [U256].
+    pub const fn as_be_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Constructs [U256] from an array of bytes. Big endian byte order is assumed.
+    pub const fn from_be_bytes(src: [u8; 32]) -> Self {
+        Self(src)
+    }
+}
+
+impl From<Felt> for U256 {
+    fn from(felt: Felt) -> Self {
+        Self(felt.to_be_bytes())
+    }
+}
+
+impl From<u128> for U256 {
+    fn from(src: u128) -> Self {
+        let mut buf = [0u8; 32];
+        buf[16..].copy_from_slice(&src.to_be_bytes());
+        Self(buf)
+    }
+}
+
+impl std::fmt::Debug for U256 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "U256(0x")?;
+        self.0.iter().try_for_each(|&b| write!(f, "{:02X}", b))?;
+        write!(f, ")")
+    }
+}
+
 impl From<StarknetBlockNumber> for BlockId {
     fn from(number: StarknetBlockNumber) -> Self {
         Self::Number(number)
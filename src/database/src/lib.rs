@@ -1,7 +1,8 @@
 use mongodb::{bson::doc, options::ClientOptions, Client};
 use dotenv::dotenv;
+use std::collections::HashMap;
 use std::env;
-use moso_events::StarknetEmittedEvent;
+use moso_events::{IndexedEvent, StarknetEmittedEvent};
 pub struct MosoDb {
     client: Client,
 }
@@ -20,5 +21,27 @@ impl MosoDb{
         let collection = db.collection("events");
         collection.insert_many(events, None).await.unwrap();
     }
+    /// Inserts token events into `events` and custom events into their own collections.
+    pub async fn insert_indexed_events(&self, events: Vec<IndexedEvent>) -> () {
+        let mut tokens = Vec::new();
+        let mut custom = HashMap::<String, Vec<_>>::new();
+        for event in events {
+            match event {
+                IndexedEvent::Token(event) => tokens.push(event),
+                IndexedEvent::Custom { collection, document } => {
+                    custom.entry(collection).or_default().push(document)
+                }
+            }
+        }
+
+        // An empty batch is rejected by the server.
+        if !tokens.is_empty() {
+            self.insert_events(tokens).await;
+        }
+        let db = self.client.database("moso");
+        for (collection, documents) in custom {
+            db.collection(&collection).insert_many(documents, None).await.unwrap();
+        }
+    }
 
 }
\ No newline at end of file
@@ -0,0 +1,197 @@
+//! Cache of parsed contract ABIs, shared across [get_events](crate::StarknetEventsTable::get_events)
+//! calls so that each class definition is decompressed and parsed only once.
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use lru::LruCache;
+use pathfinder_common::{ClassHash, EntryPoint, EventKey};
+
+use crate::class::standard::Classification;
+use crate::class::{AbiWarning, ContractAbiEntry, EventAbiEntry};
+
+/// The parsed ABI of a class together with the selectors of its events.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClassAbi {
+    pub abi: Vec<ContractAbiEntry>,
+    /// The token standard the class implements.
+    pub classification: Classification,
+    /// Problems found while parsing the ABI, the entries they concern are not in `abi`.
+    pub warnings: Vec<AbiWarning>,
+    /// ABI entries which are not recognised, as they appear in the definition.
+    pub unknown_abi_entries: Vec<serde_json::Value>,
+    /// Event selectors paired with the position of the event definition in `abi`.
+    selectors: Vec<(EventKey, usize)>,
+}
+
+impl ClassAbi {
+    pub fn new(abi: Vec<ContractAbiEntry>) -> Self {
+        let selectors = abi
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| match entry {
+                ContractAbiEntry::Event(event) => {
+                    let selector = EntryPoint::hashed(event.name.as_bytes());
+                    Some((EventKey(selector.0), i))
+                }
+                _ => None,
+            })
+            .collect();
+        let classification = Classification::from_abi(&abi);
+
+        Self {
+            abi,
+            classification,
+            warnings: Vec::new(),
+            unknown_abi_entries: Vec::new(),
+            selectors,
+        }
+    }
+
+    /// Keeps the problems [ContractClass](crate::class::ContractClass) found in the ABI, so that
+    /// they can be reported.
+    pub fn with_warnings(
+        mut self,
+        warnings: Vec<AbiWarning>,
+        unknown_abi_entries: Vec<serde_json::Value>,
+    ) -> Self {
+        self.warnings = warnings;
+        self.unknown_abi_entries = unknown_abi_entries;
+        self
+    }
+
+    /// Finds the event definition whose selector matches the first key of the event.
+    pub fn event(&self, keys: &[EventKey]) -> Option<&EventAbiEntry> {
+        let selector = keys.first()?;
+
+        self.selectors
+            .iter()
+            .find(|(key, _)| key == selector)
+            .and_then(|(_, i)| match &self.abi[*i] {
+                ContractAbiEntry::Event(event) => Some(event),
+                _ => None,
+            })
+    }
+
+    /// Whether the class is a proxy forwarding calls to an implementation through its
+    /// `__default__` entry point.
+    pub fn is_proxy(&self) -> bool {
+        let forwards = self.abi.iter().any(|entry| {
+            matches!(entry, ContractAbiEntry::Function(function) if function.name == "__default__")
+        });
+
+        forwards && self.event_by_name(crate::proxy::UPGRADED).is_some()
+    }
+
+    /// Finds the event definition called `name`.
+    pub fn event_by_name(&self, name: &str) -> Option<&EventAbiEntry> {
+        self.abi.iter().find_map(|entry| match entry {
+            ContractAbiEntry::Event(event) if event.name == name => Some(event),
+            _ => None,
+        })
+    }
+}
+
+/// A least recently used cache of [ClassAbi]s keyed by [ClassHash].
+pub struct AbiCache {
+    classes: Mutex<LruCache<ClassHash, Arc<ClassAbi>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl AbiCache {
+    pub const DEFAULT_CAPACITY: usize = 1024;
+
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            classes: Mutex::new(LruCache::new(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the ABI of `class_hash`, calling `load` and caching its result on a miss.
+    /// Failed loads are not cached.
+    pub fn get_or_load<E>(
+        &self,
+        class_hash: ClassHash,
+        load: impl FnOnce() -> Result<ClassAbi, E>,
+    ) -> Result<Arc<ClassAbi>, E> {
+        let cached = self.lock().get(&class_hash).cloned();
+        if let Some(abi) = cached {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(abi);
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        // The lock is not held while loading, a concurrent miss for the same class only results
+        // in the ABI being parsed twice.
+        let abi = Arc::new(load()?);
+        self.lock().put(class_hash, abi.clone());
+
+        Ok(abi)
+    }
+
+    /// Number of lookups served from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of lookups which had to load the ABI.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// The warnings of the cached classes whose ABI was not fully understood.
+    pub fn warnings(&self) -> Vec<(ClassHash, Vec<AbiWarning>)> {
+        self.lock()
+            .iter()
+            .filter(|(_, abi)| !abi.warnings.is_empty())
+            .map(|(class_hash, abi)| (*class_hash, abi.warnings.clone()))
+            .collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LruCache<ClassHash, Arc<ClassAbi>>> {
+        // The cache holds no invariants a panicking holder could break.
+        self.classes
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Default for AbiCache {
+    fn default() -> Self {
+        Self::new(NonZeroUsize::new(Self::DEFAULT_CAPACITY).expect("capacity is not zero"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use stark_hash::Felt;
+
+    use super::*;
+
+    #[test]
+    fn get_or_load() {
+        let cache = AbiCache::default();
+        let class_hash = ClassHash(Felt::from_u64(1));
+
+        let error = cache
+            .get_or_load(class_hash, || Err("unreadable definition"))
+            .unwrap_err();
+        assert_eq!(error, "unreadable definition");
+        assert_eq!((cache.hits(), cache.misses()), (0, 1));
+
+        // The failed load was not cached, so the class is loaded again.
+        let abi = cache
+            .get_or_load::<()>(class_hash, || Ok(ClassAbi::new(Vec::new())))
+            .unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (0, 2));
+
+        let cached = cache
+            .get_or_load::<()>(class_hash, || panic!("class is cached"))
+            .unwrap();
+        assert!(Arc::ptr_eq(&abi, &cached));
+        assert_eq!((cache.hits(), cache.misses()), (1, 2));
+    }
+}
@@ -1,3 +1,8 @@
+pub mod cairo_type;
+pub mod decode;
+mod sierra;
+pub mod standard;
+
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
 
@@ -7,26 +12,133 @@ impl ContractClass {
         let json_obj = json
             .as_object_mut()
             .context("Class definition is not a json object")?;
+
+        let mut class = ContractClass {
+            sierra: json_obj.contains_key("sierra_program"),
+            ..Default::default()
+        };
         // ABI is optional.
-        let abi = json_obj.get_mut("abi").and_then(|json| {
-            let json = json.take();
-            serde_json::from_value::<Vec<ContractAbiEntry>>(json).ok()
-        });
-        
-     
-
-        Ok(ContractClass {
-            abi,
-        })
+        let abi = match json_obj.get_mut("abi") {
+            Some(abi) => abi.take(),
+            None => return Ok(class),
+        };
+        let entries = match abi {
+            serde_json::Value::Array(entries) => entries,
+            // Sierra classes carry their ABI as a JSON encoded string.
+            serde_json::Value::String(abi) => {
+                class.sierra = true;
+                match serde_json::from_str(&abi) {
+                    Ok(serde_json::Value::Array(entries)) => entries,
+                    _ => {
+                        class.warnings.push(AbiWarning::NotAnArray);
+                        return Ok(class);
+                    }
+                }
+            }
+            serde_json::Value::Null => return Ok(class),
+            _ => {
+                class.warnings.push(AbiWarning::NotAnArray);
+                return Ok(class);
+            }
+        };
+
+        // Entries are parsed one by one, so that an entry this parser does not know about does
+        // not hide the rest of the ABI.
+        let mut abi = Vec::with_capacity(entries.len());
+        let mut sierra_abi = Vec::new();
+        for (index, entry) in entries.into_iter().enumerate() {
+            let parsed = if class.sierra {
+                sierra::SierraAbiEntry::deserialize(&entry).map(|entry| sierra_abi.push(entry))
+            } else {
+                ContractAbiEntry::from_json(&entry).map(|parsed| {
+                    let mut fields = Vec::new();
+                    if let Ok(serialized) = serde_json::to_value(&parsed) {
+                        ignored_fields(&entry, &serialized, "", &mut fields);
+                    }
+                    if !fields.is_empty() {
+                        class
+                            .warnings
+                            .push(AbiWarning::IgnoredFields { index, fields });
+                    }
+                    abi.push(parsed)
+                })
+            };
+            if let Err(error) = parsed {
+                class.warnings.push(AbiWarning::UnknownEntry {
+                    index,
+                    error: error.to_string(),
+                });
+                class.unknown_abi_entries.push(entry);
+            }
+        }
+        let (converted, warnings) = sierra::convert(sierra_abi);
+        abi.extend(converted);
+        class.warnings.extend(warnings);
+        class.abi = Some(abi);
+
+        Ok(class)
     }
 }
 
+/// Collects the paths of the fields of an ABI entry which were not parsed, found by comparing
+/// the entry to the serialization of its parsed form.
+fn ignored_fields(
+    entry: &serde_json::Value,
+    parsed: &serde_json::Value,
+    path: &str,
+    ignored: &mut Vec<String>,
+) {
+    match (entry, parsed) {
+        (serde_json::Value::Object(entry), serde_json::Value::Object(parsed)) => {
+            for (key, value) in entry {
+                let field = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                match parsed.get(key) {
+                    Some(parsed) => ignored_fields(value, parsed, &field, ignored),
+                    // Optional fields which are null are not serialized.
+                    None if value.is_null() => {}
+                    None => ignored.push(field),
+                }
+            }
+        }
+        (serde_json::Value::Array(entry), serde_json::Value::Array(parsed)) => {
+            for (i, (entry, parsed)) in entry.iter().zip(parsed).enumerate() {
+                ignored_fields(entry, parsed, &format!("{path}[{i}]"), ignored);
+            }
+        }
+        _ => {}
+    }
+}
 
-#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub struct ContractClass {
     pub abi: Option<Vec<ContractAbiEntry>>,
+    /// ABI entries which are not recognised, as they appear in the definition.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unknown_abi_entries: Vec<serde_json::Value>,
+    /// Problems found while parsing the ABI.
+    #[serde(skip)]
+    pub warnings: Vec<AbiWarning>,
+    /// Whether the class was compiled from Cairo 1 to Sierra, its ABI is converted into the
+    /// Cairo 0 model.
+    #[serde(skip)]
+    pub sierra: bool,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AbiWarning {
+    /// The ABI is not a list of entries and was left out.
+    NotAnArray,
+    /// The entry at `index` is not a known function, event or struct.
+    UnknownEntry { index: usize, error: String },
+    /// The entry at `index` was parsed without these fields, which it is not known to have.
+    IgnoredFields { index: usize, fields: Vec<String> },
+    /// The Sierra event variant `name` cannot be matched by its selector and was left out.
+    UnsupportedEvent { name: String },
+}
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(untagged)]
@@ -37,6 +149,21 @@ pub enum ContractAbiEntry {
     Struct(StructAbiEntry),
 }
 
+impl ContractAbiEntry {
+    /// Parses an entry as the kind its `type` names, so that the error explains what is wrong
+    /// with it rather than only that it matches none of the kinds.
+    fn from_json(entry: &serde_json::Value) -> serde_json::Result<Self> {
+        match entry.get("type").and_then(|r#type| r#type.as_str()) {
+            Some("function" | "l1_handler" | "constructor") => {
+                FunctionAbiEntry::deserialize(entry).map(ContractAbiEntry::Function)
+            }
+            Some("event") => EventAbiEntry::deserialize(entry).map(ContractAbiEntry::Event),
+            Some("struct") => StructAbiEntry::deserialize(entry).map(ContractAbiEntry::Struct),
+            _ => ContractAbiEntry::deserialize(entry),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 #[serde(deny_unknown_fields)]
@@ -64,7 +191,6 @@ pub enum FunctionAbiType {
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
-#[serde(deny_unknown_fields)]
 pub struct StructAbiEntry {
     pub r#type: StructAbiType,
     pub name: String,
@@ -72,8 +198,17 @@ pub struct StructAbiEntry {
     pub members: Vec<StructMember>,
 }
 
+impl StructAbiEntry {
+    /// Returns the type of the member called `name`.
+    pub fn member_type(&self, name: &str) -> Option<&str> {
+        self.members
+            .iter()
+            .find(|member| member.typed_parameter_name == name)
+            .map(|member| member.typed_parameter_type.as_str())
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
-#[serde(deny_unknown_fields)]
 pub struct StructMember {
     // Serde does not support deny_unknown_fields + flatten, so we
     // flatten TypedParameter manually here.
@@ -85,7 +220,6 @@ pub struct StructMember {
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
-#[serde(deny_unknown_fields)]
 pub struct EventAbiEntry {
     r#type: EventAbiType,
     pub name: String,
@@ -110,28 +244,75 @@ pub struct EventAbiEntry {
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
-#[serde(deny_unknown_fields)]
 pub struct FunctionAbiEntry {
-    r#type: FunctionAbiType,
-    name: String,
+    pub r#type: FunctionAbiType,
+    pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
-    inputs: Option<Vec<TypedParameter>>,
+    pub inputs: Option<Vec<TypedParameter>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
-    outputs: Option<Vec<TypedParameter>>,
+    pub outputs: Option<Vec<TypedParameter>>,
     // This is not part of the JSON-RPC specification, but because we use these
     // types to parse the `starknet_estimateFee` request and then serialize the
     // class definition in the transaction for the Python layer we have to keep
     // this property when serializing.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     #[serde(rename = "stateMutability")]
-    state_mutability: Option<String>,
+    pub state_mutability: Option<String>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
-#[serde(deny_unknown_fields)]
 pub struct TypedParameter {
     pub name: String,
-    r#type: String,
+    pub r#type: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_entries_do_not_hide_the_abi() {
+        let definition = br#"{"abi": [
+            {
+                "type": "event",
+                "name": "Transfer",
+                "keys": [],
+                "data": [{"name": "from_", "type": "felt", "indexed": false}],
+                "docs": "unexpected field"
+            },
+            {"type": "interface", "name": "IERC721"},
+            {
+                "type": "function",
+                "name": "ownerOf",
+                "inputs": [{"name": "tokenId", "type": "Uint256"}],
+                "outputs": [{"name": "owner", "type": "felt"}],
+                "stateMutability": "view"
+            }
+        ]}"#;
+
+        let class = ContractClass::from_definition_bytes(definition).unwrap();
+
+        let abi = class.abi.unwrap();
+        assert!(matches!(
+            &abi[..],
+            [ContractAbiEntry::Event(event), ContractAbiEntry::Function(function)]
+                if event.name == "Transfer" && function.name == "ownerOf"
+        ));
+        assert_eq!(class.unknown_abi_entries.len(), 1);
+        assert_eq!(class.unknown_abi_entries[0]["name"], "IERC721");
+        assert_eq!(class.warnings.len(), 2);
+        assert_eq!(
+            class.warnings[0],
+            AbiWarning::IgnoredFields {
+                index: 0,
+                fields: vec!["data[0].indexed".to_owned(), "docs".to_owned()]
+            }
+        );
+        assert!(matches!(
+            class.warnings[1],
+            AbiWarning::UnknownEntry { index: 1, .. }
+        ));
+    }
 }
@@ -0,0 +1,346 @@
+//! Parser for the Cairo 0 type strings of ABI parameters and struct members, such as `felt`,
+//! `felt*`, `Uint256`, `(felt, felt)` or `(x: felt, y: felt)`.
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use super::{StructAbiEntry, TypedParameter};
+
+const FELT: &str = "felt";
+const UINT256: &str = "Uint256";
+
+/// Upper bound on the nesting of tuples and pointers within a type.
+const MAX_DEPTH: usize = 32;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CairoType {
+    Felt,
+    /// A pointer, an event member of this type is an array preceded by its `_len` member.
+    Pointer(Box<CairoType>),
+    Tuple(Vec<TupleMember>),
+    /// A struct, resolved through the [StructAbiEntry] definitions of the contract.
+    Named(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TupleMember {
+    pub name: Option<String>,
+    pub r#type: CairoType,
+}
+
+#[derive(Clone, Debug, thiserror::Error, PartialEq, Eq)]
+pub enum TypeError {
+    #[error("invalid type {input:?} at position {position}")]
+    Syntax { input: String, position: usize },
+    #[error("unknown struct {0}")]
+    UnknownStruct(String),
+    #[error("size of the type overflows")]
+    TooLarge,
+}
+
+impl CairoType {
+    pub fn parse(input: &str) -> Result<Self, TypeError> {
+        let mut parser = Parser {
+            input,
+            position: 0,
+            depth: 0,
+        };
+        let r#type = parser.r#type()?;
+        parser.skip_whitespace();
+        if parser.position != input.len() {
+            return Err(parser.error());
+        }
+
+        Ok(r#type)
+    }
+
+    /// Number of felts a value of this type occupies.
+    ///
+    /// A pointer is a single memory address, the elements of an event array are counted by the
+    /// decoder from its `_len` member. `Uint256` is two felts even if the ABI does not define
+    /// it.
+    pub fn size(&self, structs: &HashMap<&str, &StructAbiEntry>) -> Result<usize, TypeError> {
+        match self {
+            CairoType::Felt | CairoType::Pointer(_) => Ok(1),
+            CairoType::Tuple(members) => members.iter().try_fold(0usize, |size, member| {
+                size.checked_add(member.r#type.size(structs)?)
+                    .ok_or(TypeError::TooLarge)
+            }),
+            CairoType::Named(name) => match structs.get(name.as_str()) {
+                Some(entry) => usize::try_from(entry.size).map_err(|_| TypeError::TooLarge),
+                None if name == UINT256 => Ok(2),
+                None => Err(TypeError::UnknownStruct(name.clone())),
+            },
+        }
+    }
+}
+
+impl FromStr for CairoType {
+    type Err = TypeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for CairoType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CairoType::Felt => f.write_str(FELT),
+            CairoType::Pointer(r#type) => write!(f, "{type}*"),
+            CairoType::Tuple(members) => {
+                f.write_str("(")?;
+                for (i, member) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    if let Some(name) = &member.name {
+                        write!(f, "{name}: ")?;
+                    }
+                    write!(f, "{}", member.r#type)?;
+                }
+                f.write_str(")")
+            }
+            CairoType::Named(name) => f.write_str(name),
+        }
+    }
+}
+
+impl TypedParameter {
+    pub fn cairo_type(&self) -> Result<CairoType, TypeError> {
+        CairoType::parse(&self.r#type)
+    }
+}
+
+/// Recursive descent parser over
+///
+/// ```text
+/// type   = (tuple | name) "*"*
+/// tuple  = "(" [member ("," member)* [","]] ")"
+/// member = [identifier ":"] type
+/// name   = identifier ("::" identifier)*
+/// ```
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+    /// Number of enclosing tuples, bounded by [MAX_DEPTH].
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn r#type(&mut self) -> Result<CairoType, TypeError> {
+        self.skip_whitespace();
+        let mut r#type = if self.eat("(") {
+            if self.depth == MAX_DEPTH {
+                return Err(self.error());
+            }
+            self.depth += 1;
+            let tuple = self.tuple()?;
+            self.depth -= 1;
+            tuple
+        } else {
+            match self.name()? {
+                name if name == FELT => CairoType::Felt,
+                name => CairoType::Named(name),
+            }
+        };
+
+        let mut pointers = 0;
+        loop {
+            self.skip_whitespace();
+            if !self.eat("*") {
+                break;
+            }
+            pointers += 1;
+            if pointers > MAX_DEPTH {
+                return Err(self.error());
+            }
+            r#type = CairoType::Pointer(Box::new(r#type));
+        }
+
+        Ok(r#type)
+    }
+
+    fn tuple(&mut self) -> Result<CairoType, TypeError> {
+        let mut members = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.eat(")") {
+                break;
+            }
+            members.push(self.member()?);
+
+            self.skip_whitespace();
+            if !self.eat(",") {
+                self.skip_whitespace();
+                if !self.eat(")") {
+                    return Err(self.error());
+                }
+                break;
+            }
+        }
+
+        Ok(CairoType::Tuple(members))
+    }
+
+    fn member(&mut self) -> Result<TupleMember, TypeError> {
+        // Only a single colon separates a member name from its type, a double colon is part of
+        // a path.
+        let start = self.position;
+        if let Some(name) = self.identifier() {
+            self.skip_whitespace();
+            if self.rest().starts_with(':') && !self.rest().starts_with("::") {
+                self.position += 1;
+                return Ok(TupleMember {
+                    name: Some(name.to_owned()),
+                    r#type: self.r#type()?,
+                });
+            }
+        }
+        self.position = start;
+
+        Ok(TupleMember {
+            name: None,
+            r#type: self.r#type()?,
+        })
+    }
+
+    fn name(&mut self) -> Result<String, TypeError> {
+        let start = self.position;
+        self.identifier().ok_or_else(|| self.error())?;
+        while self.eat("::") {
+            self.identifier().ok_or_else(|| self.error())?;
+        }
+
+        Ok(self.input[start..self.position].to_owned())
+    }
+
+    /// An identifier, dots are allowed for the full paths of Cairo 0 structs.
+    fn identifier(&mut self) -> Option<&'a str> {
+        let rest = self.rest();
+        if !rest.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            return None;
+        }
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+            .unwrap_or(rest.len());
+        self.position += len;
+
+        Some(&rest[..len])
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        let found = self.rest().starts_with(token);
+        if found {
+            self.position += token.len();
+        }
+        found
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.position..]
+    }
+
+    fn error(&self) -> TypeError {
+        TypeError::Syntax {
+            input: self.input.to_owned(),
+            position: self.position,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn named(name: Option<&str>, r#type: CairoType) -> TupleMember {
+        TupleMember {
+            name: name.map(str::to_owned),
+            r#type,
+        }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(CairoType::parse("felt"), Ok(CairoType::Felt));
+        assert_eq!(
+            CairoType::parse("Uint256**"),
+            Ok(CairoType::Pointer(Box::new(CairoType::Pointer(Box::new(
+                CairoType::Named("Uint256".to_owned())
+            )))))
+        );
+        assert_eq!(
+            CairoType::parse("(x: felt, (felt, Point)*)"),
+            Ok(CairoType::Tuple(vec![
+                named(Some("x"), CairoType::Felt),
+                named(
+                    None,
+                    CairoType::Pointer(Box::new(CairoType::Tuple(vec![
+                        named(None, CairoType::Felt),
+                        named(None, CairoType::Named("Point".to_owned())),
+                    ])))
+                ),
+            ]))
+        );
+        assert_eq!(
+            CairoType::parse("(x: felt, (felt, Point)*)")
+                .unwrap()
+                .to_string(),
+            "(x: felt, (felt, Point)*)"
+        );
+        assert_eq!(
+            CairoType::parse("(felt, felt"),
+            Err(TypeError::Syntax {
+                input: "(felt, felt".to_owned(),
+                position: 11
+            })
+        );
+    }
+
+    #[test]
+    fn parse_deeply_nested() {
+        let depth = MAX_DEPTH + 1;
+        let tuples = format!("{}felt{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(matches!(
+            CairoType::parse(&tuples),
+            Err(TypeError::Syntax { .. })
+        ));
+        let pointers = format!("felt{}", "*".repeat(depth));
+        assert!(matches!(
+            CairoType::parse(&pointers),
+            Err(TypeError::Syntax { .. })
+        ));
+        assert!(CairoType::parse(&format!("felt{}", "*".repeat(MAX_DEPTH))).is_ok());
+    }
+
+    #[test]
+    fn size() {
+        let point: StructAbiEntry = serde_json::from_str(
+            r#"{
+                "type": "struct",
+                "name": "Point",
+                "size": 2,
+                "members": [
+                    {"name": "x", "type": "felt", "offset": 0},
+                    {"name": "y", "type": "felt", "offset": 1}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let structs = HashMap::from([("Point", &point)]);
+
+        let size = |r#type: &str| CairoType::parse(r#type).unwrap().size(&structs);
+
+        assert_eq!(size("felt*"), Ok(1));
+        assert_eq!(size("(Point, Uint256, felt)"), Ok(5));
+        assert_eq!(
+            size("Line"),
+            Err(TypeError::UnknownStruct("Line".to_owned()))
+        );
+    }
+}
@@ -0,0 +1,525 @@
+//! Decoding of raw event data into typed values, driven by the event's ABI definition.
+use std::collections::HashMap;
+
+use pathfinder_common::{EventData, EventKey, U256};
+use stark_hash::Felt;
+
+use super::cairo_type::{CairoType, TypeError};
+use super::{ContractAbiEntry, EventAbiEntry, StructAbiEntry, TypedParameter};
+
+const UINT256: &str = "Uint256";
+
+/// Upper bound on the nesting of structs and tuples, which also stops recursive struct
+/// definitions.
+const MAX_DEPTH: usize = 32;
+
+/// A single decoded value of an event member.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodedValue {
+    Felt(Felt),
+    Uint256(U256),
+    Array(Vec<DecodedValue>),
+    Tuple(Vec<DecodedValue>),
+    Struct(Vec<DecodedMember>),
+}
+
+/// A named member of a decoded event or struct.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodedMember {
+    pub name: String,
+    pub value: DecodedValue,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodedEvent {
+    pub name: String,
+    pub members: Vec<DecodedMember>,
+}
+
+impl DecodedEvent {
+    /// Returns the value of the member called `name`.
+    pub fn get(&self, name: &str) -> Option<&DecodedValue> {
+        self.members
+            .iter()
+            .find(|member| member.name == name)
+            .map(|member| &member.value)
+    }
+}
+
+#[derive(Clone, Debug, thiserror::Error, PartialEq, Eq)]
+pub enum DecodeError {
+    #[error("event data has {actual} elements, expected at least {expected}")]
+    MissingData { expected: usize, actual: usize },
+    #[error("event data has {actual} elements, only {consumed} were decoded")]
+    UnconsumedData { consumed: usize, actual: usize },
+    #[error("event has {actual} keys, expected at least {expected}")]
+    MissingKeys { expected: usize, actual: usize },
+    #[error("event has {actual} keys, only {consumed} were decoded")]
+    UnconsumedKeys { consumed: usize, actual: usize },
+    #[error("array {0} is not preceded by a felt {0}_len member")]
+    MissingArrayLength(String),
+    #[error("length of array {0} is too big")]
+    InvalidArrayLength(String),
+    #[error("Uint256 at data offset {0} has a limb with more than 128 bits")]
+    InvalidUint256(usize),
+    #[error("member {member} of struct {name} is outside of its size")]
+    InvalidStructMember { name: String, member: String },
+    #[error("types are nested more than {MAX_DEPTH} levels deep")]
+    TooDeep,
+    #[error(transparent)]
+    InvalidType(#[from] TypeError),
+}
+
+/// Decodes events according to their [EventAbiEntry], resolving struct types with the
+/// [StructAbiEntry] definitions of the contract.
+///
+/// Arrays follow the Cairo convention of a `foo_len: felt` member directly preceding the
+/// `foo: T*` member. Structs are laid out according to their `size` and member `offset`s.
+pub struct AbiDecoder<'a> {
+    structs: HashMap<&'a str, &'a StructAbiEntry>,
+}
+
+impl<'a> AbiDecoder<'a> {
+    /// Creates a decoder using the struct definitions found in `abi`.
+    pub fn new(abi: &'a [ContractAbiEntry]) -> Self {
+        Self::with_structs(abi.iter().filter_map(|entry| match entry {
+            ContractAbiEntry::Struct(entry) => Some(entry),
+            _ => None,
+        }))
+    }
+
+    pub fn with_structs(structs: impl IntoIterator<Item = &'a StructAbiEntry>) -> Self {
+        Self {
+            structs: structs
+                .into_iter()
+                .map(|entry| (entry.name.as_str(), entry))
+                .collect(),
+        }
+    }
+
+    /// Decodes an event according to its members, the members marked as keys are read from the
+    /// `keys` following the selector and the remaining members from `data`.
+    ///
+    /// The decoded members are ordered keys first.
+    pub fn decode(
+        &self,
+        event: &EventAbiEntry,
+        keys: &[EventKey],
+        data: &[EventData],
+    ) -> Result<DecodedEvent, DecodeError> {
+        let key_members = event.keys.as_deref().unwrap_or_default();
+        let data_members = event.data.as_deref().unwrap_or_default();
+        let mut decoded = Vec::with_capacity(key_members.len() + data_members.len());
+
+        // The first key is the selector of the event.
+        let keys: Vec<_> = keys.iter().skip(1).map(|key| EventData(key.0)).collect();
+        self.decode_members(key_members, &keys, &mut decoded)
+            .map_err(|error| match error {
+                // Count the selector in the reported number of keys.
+                DecodeError::MissingData { expected, actual } => DecodeError::MissingKeys {
+                    expected: expected + 1,
+                    actual: actual + 1,
+                },
+                DecodeError::UnconsumedData { consumed, actual } => DecodeError::UnconsumedKeys {
+                    consumed: consumed + 1,
+                    actual: actual + 1,
+                },
+                error => error,
+            })?;
+        self.decode_members(data_members, data, &mut decoded)?;
+
+        Ok(DecodedEvent {
+            name: event.name.clone(),
+            members: decoded,
+        })
+    }
+
+    /// Decodes `members` from all of `data`, appending them to `decoded`.
+    fn decode_members(
+        &self,
+        members: &[TypedParameter],
+        data: &[EventData],
+        decoded: &mut Vec<DecodedMember>,
+    ) -> Result<(), DecodeError> {
+        let mut reader = Reader { data, offset: 0 };
+
+        for member in members {
+            let value = match member.cairo_type()? {
+                CairoType::Pointer(element_type) => {
+                    let len = Self::array_len(decoded, &member.name)?;
+                    // Every element takes at least one felt, this also stops lengths which
+                    // would only be caught after reading the whole data.
+                    let remaining = data.len() - reader.offset;
+                    if len > remaining {
+                        return Err(DecodeError::MissingData {
+                            expected: reader.offset.saturating_add(len),
+                            actual: data.len(),
+                        });
+                    }
+                    let elements = (0..len)
+                        .map(|_| self.decode_value(&element_type, &mut reader, 0))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    DecodedValue::Array(elements)
+                }
+                r#type => self.decode_value(&r#type, &mut reader, 0)?,
+            };
+
+            decoded.push(DecodedMember {
+                name: member.name.clone(),
+                value,
+            });
+        }
+
+        if reader.offset != data.len() {
+            return Err(DecodeError::UnconsumedData {
+                consumed: reader.offset,
+                actual: data.len(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Looks up the length of array `name` from the already decoded `name_len` member.
+    fn array_len(decoded: &[DecodedMember], name: &str) -> Result<usize, DecodeError> {
+        let len_name = format!("{name}_len");
+        let len = decoded
+            .iter()
+            .rev()
+            .find(|member| member.name == len_name)
+            .ok_or_else(|| DecodeError::MissingArrayLength(name.to_owned()))?;
+
+        match &len.value {
+            DecodedValue::Felt(len) => {
+                felt_to_usize(len).ok_or_else(|| DecodeError::InvalidArrayLength(name.to_owned()))
+            }
+            _ => Err(DecodeError::MissingArrayLength(name.to_owned())),
+        }
+    }
+
+    /// Decodes a value of `type`, nested `depth` levels deep in structs and tuples.
+    fn decode_value(
+        &self,
+        r#type: &CairoType,
+        reader: &mut Reader<'_>,
+        depth: usize,
+    ) -> Result<DecodedValue, DecodeError> {
+        if depth > MAX_DEPTH {
+            return Err(DecodeError::TooDeep);
+        }
+
+        match r#type {
+            CairoType::Felt => Ok(DecodedValue::Felt(reader.next()?)),
+            // Pointers within structs and tuples are plain memory addresses.
+            CairoType::Pointer(_) => Ok(DecodedValue::Felt(reader.next()?)),
+            CairoType::Tuple(members) => members
+                .iter()
+                .map(|member| self.decode_value(&member.r#type, reader, depth + 1))
+                .collect::<Result<Vec<_>, _>>()
+                .map(DecodedValue::Tuple),
+            CairoType::Named(name) if name == UINT256 => {
+                let offset = reader.offset;
+                let low = reader.next()?;
+                let high = reader.next()?;
+                U256::from_limbs(low, high)
+                    .map(DecodedValue::Uint256)
+                    .map_err(|_| DecodeError::InvalidUint256(offset))
+            }
+            CairoType::Named(name) => {
+                let entry = self
+                    .structs
+                    .get(name.as_str())
+                    .ok_or_else(|| TypeError::UnknownStruct(name.clone()))?;
+                self.decode_struct(entry, reader, depth)
+            }
+        }
+    }
+
+    /// Decodes a struct by the offsets of its members, checking that each member fits within
+    /// the size of the struct.
+    fn decode_struct(
+        &self,
+        entry: &StructAbiEntry,
+        reader: &mut Reader<'_>,
+        depth: usize,
+    ) -> Result<DecodedValue, DecodeError> {
+        let start = reader.offset;
+        let size = usize::try_from(entry.size).ok();
+        let end = size.and_then(|size| start.checked_add(size));
+        let (size, end) = match (size, end) {
+            (Some(size), Some(end)) if end <= reader.data.len() => (size, end),
+            _ => {
+                return Err(DecodeError::MissingData {
+                    expected: end.unwrap_or(usize::MAX),
+                    actual: reader.data.len(),
+                })
+            }
+        };
+
+        let members = entry
+            .members
+            .iter()
+            .map(|member| {
+                let r#type = CairoType::parse(&member.typed_parameter_type)?;
+                let member_size = r#type.size(&self.structs)?;
+                let offset = usize::try_from(member.offset).ok();
+                let member_end = offset.and_then(|offset| offset.checked_add(member_size));
+                let offset = match (offset, member_end) {
+                    (Some(offset), Some(member_end)) if member_end <= size => offset,
+                    _ => {
+                        return Err(DecodeError::InvalidStructMember {
+                            name: entry.name.clone(),
+                            member: member.typed_parameter_name.clone(),
+                        })
+                    }
+                };
+
+                let mut member_reader = Reader {
+                    data: reader.data,
+                    offset: start + offset,
+                };
+                let value = self.decode_value(&r#type, &mut member_reader, depth + 1)?;
+
+                Ok(DecodedMember {
+                    name: member.typed_parameter_name.clone(),
+                    value,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        reader.offset = end;
+
+        Ok(DecodedValue::Struct(members))
+    }
+}
+
+/// Sequential reader over event data.
+struct Reader<'a> {
+    data: &'a [EventData],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn next(&mut self) -> Result<Felt, DecodeError> {
+        let felt = self.data.get(self.offset).ok_or(DecodeError::MissingData {
+            expected: self.offset + 1,
+            actual: self.data.len(),
+        })?;
+        self.offset += 1;
+
+        Ok(felt.0)
+    }
+}
+
+/// Converts a felt holding a length or an index into a `usize`.
+pub(crate) fn felt_to_usize(felt: &Felt) -> Option<usize> {
+    let bytes = felt.as_be_bytes();
+    let (high, low) = bytes.split_at(32 - std::mem::size_of::<u64>());
+    if high.iter().any(|b| *b != 0) {
+        return None;
+    }
+
+    let low = u64::from_be_bytes(low.try_into().expect("slice is 8 bytes long"));
+    usize::try_from(low).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pathfinder_common::EntryPoint;
+
+    fn abi() -> Vec<ContractAbiEntry> {
+        serde_json::from_str(
+            r#"[
+                {
+                    "type": "struct",
+                    "name": "Uint256",
+                    "size": 2,
+                    "members": [
+                        {"name": "low", "type": "felt", "offset": 0},
+                        {"name": "high", "type": "felt", "offset": 1}
+                    ]
+                },
+                {
+                    "type": "struct",
+                    "name": "Listing",
+                    "size": 3,
+                    "members": [
+                        {"name": "seller", "type": "felt", "offset": 0},
+                        {"name": "price", "type": "Uint256", "offset": 1}
+                    ]
+                },
+                {
+                    "type": "event",
+                    "name": "Listed",
+                    "keys": [],
+                    "data": [
+                        {"name": "listing", "type": "Listing"},
+                        {"name": "tags_len", "type": "felt"},
+                        {"name": "tags", "type": "felt*"}
+                    ]
+                }
+            ]"#,
+        )
+        .unwrap()
+    }
+
+    fn selector() -> EventKey {
+        EventKey(EntryPoint::hashed(b"Listed").0)
+    }
+
+    fn data(felts: &[u64]) -> Vec<EventData> {
+        felts
+            .iter()
+            .map(|f| EventData(Felt::from_u64(*f)))
+            .collect()
+    }
+
+    fn event(abi: &[ContractAbiEntry]) -> &EventAbiEntry {
+        abi.iter()
+            .find_map(|entry| match entry {
+                ContractAbiEntry::Event(event) => Some(event),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn decode_struct_and_array() {
+        let abi = abi();
+        let decoder = AbiDecoder::new(&abi);
+
+        let decoded = decoder
+            .decode(event(&abi), &[selector()], &data(&[7, 100, 0, 2, 8, 9]))
+            .unwrap();
+
+        assert_eq!(decoded.name, "Listed");
+        assert_eq!(
+            decoded.get("listing"),
+            Some(&DecodedValue::Struct(vec![
+                DecodedMember {
+                    name: "seller".to_owned(),
+                    value: DecodedValue::Felt(Felt::from_u64(7)),
+                },
+                DecodedMember {
+                    name: "price".to_owned(),
+                    value: DecodedValue::Uint256(U256::from(100u128)),
+                },
+            ]))
+        );
+        assert_eq!(
+            decoded.get("tags"),
+            Some(&DecodedValue::Array(vec![
+                DecodedValue::Felt(Felt::from_u64(8)),
+                DecodedValue::Felt(Felt::from_u64(9)),
+            ]))
+        );
+    }
+
+    #[test]
+    fn decode_truncated_array() {
+        let abi = abi();
+        let decoder = AbiDecoder::new(&abi);
+
+        let error = decoder
+            .decode(event(&abi), &[selector()], &data(&[7, 100, 0, 3, 8, 9]))
+            .unwrap_err();
+
+        assert_eq!(
+            error,
+            DecodeError::MissingData {
+                expected: 7,
+                actual: 6
+            }
+        );
+    }
+
+    #[test]
+    fn decode_malformed_structs() {
+        let decode = |size: u64, member_type: &str, offset: u64| {
+            let abi: Vec<ContractAbiEntry> = serde_json::from_value(serde_json::json!([
+                {
+                    "type": "struct",
+                    "name": "Node",
+                    "size": size,
+                    "members": [{"name": "next", "type": member_type, "offset": offset}]
+                },
+                {
+                    "type": "event",
+                    "name": "Listed",
+                    "keys": [],
+                    "data": [{"name": "node", "type": "Node"}]
+                }
+            ]))
+            .unwrap();
+            AbiDecoder::new(&abi).decode(event(&abi), &[selector()], &data(&[1, 2]))
+        };
+
+        // A struct containing itself.
+        assert_eq!(decode(1, "Node", 0), Err(DecodeError::TooDeep));
+        assert_eq!(
+            decode(u64::MAX, "felt", 0),
+            Err(DecodeError::MissingData {
+                expected: usize::MAX,
+                actual: 2
+            })
+        );
+        assert_eq!(
+            decode(2, "felt", u64::MAX),
+            Err(DecodeError::InvalidStructMember {
+                name: "Node".to_owned(),
+                member: "next".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn decode_key_members() {
+        let event: EventAbiEntry = serde_json::from_str(
+            r#"{
+                "type": "event",
+                "name": "Transfer",
+                "keys": [
+                    {"name": "from_", "type": "felt"},
+                    {"name": "to", "type": "felt"}
+                ],
+                "data": [{"name": "value", "type": "Uint256"}]
+            }"#,
+        )
+        .unwrap();
+        let decoder = AbiDecoder::with_structs([]);
+        let keys = [
+            EventKey(EntryPoint::hashed(b"Transfer").0),
+            EventKey(Felt::from_u64(1)),
+            EventKey(Felt::from_u64(2)),
+        ];
+
+        let decoded = decoder.decode(&event, &keys, &data(&[5, 0])).unwrap();
+
+        assert_eq!(
+            decoded.members,
+            vec![
+                DecodedMember {
+                    name: "from_".to_owned(),
+                    value: DecodedValue::Felt(Felt::from_u64(1)),
+                },
+                DecodedMember {
+                    name: "to".to_owned(),
+                    value: DecodedValue::Felt(Felt::from_u64(2)),
+                },
+                DecodedMember {
+                    name: "value".to_owned(),
+                    value: DecodedValue::Uint256(U256::from(5u128)),
+                },
+            ]
+        );
+        assert_eq!(
+            decoder
+                .decode(&event, &keys[..2], &data(&[5, 0]))
+                .unwrap_err(),
+            DecodeError::MissingKeys {
+                expected: 3,
+                actual: 2
+            }
+        );
+    }
+}
@@ -0,0 +1,687 @@
+//! ABI of classes compiled from Cairo 1 to Sierra, converted into the Cairo 0 model of the
+//! parent module so that the rest of the crate decodes events of both the same way.
+//!
+//! Felt sized primitives become `felt`, `core::integer::u256` becomes `Uint256`, also within
+//! tuples, and arrays and spans become a `_len` member followed by a pointer, as Cairo 0
+//! serializes them.
+use std::collections::{HashMap, HashSet};
+
+use serde::Deserialize;
+
+use super::cairo_type::{CairoType, TupleMember};
+use super::{
+    AbiWarning, ContractAbiEntry, EventAbiEntry, EventAbiType, FunctionAbiEntry, FunctionAbiType,
+    StructAbiEntry, StructAbiType, StructMember, TypedParameter,
+};
+
+const U256: &str = "core::integer::u256";
+
+/// Types which are serialized as a single felt.
+const FELT_TYPES: &[&str] = &[
+    "core::felt252",
+    "core::bool",
+    "core::integer::u8",
+    "core::integer::u16",
+    "core::integer::u32",
+    "core::integer::u64",
+    "core::integer::u128",
+    "core::integer::i8",
+    "core::integer::i16",
+    "core::integer::i32",
+    "core::integer::i64",
+    "core::integer::i128",
+    "core::starknet::contract_address::ContractAddress",
+    "core::starknet::class_hash::ClassHash",
+    "core::starknet::eth_address::EthAddress",
+    "core::starknet::storage_access::StorageAddress",
+];
+
+/// Upper bound on the nesting of structs, which also stops recursive definitions.
+const MAX_DEPTH: usize = 32;
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(super) enum SierraAbiEntry {
+    Function(SierraFunction),
+    Constructor(SierraFunction),
+    L1Handler(SierraFunction),
+    Interface {
+        #[serde(default)]
+        items: Vec<SierraAbiEntry>,
+    },
+    Impl {},
+    Struct {
+        name: String,
+        members: Vec<SierraParameter>,
+    },
+    Enum {},
+    Event(SierraEvent),
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub(super) struct SierraFunction {
+    name: String,
+    #[serde(default)]
+    inputs: Vec<SierraParameter>,
+    #[serde(default)]
+    outputs: Vec<SierraOutput>,
+    #[serde(default)]
+    state_mutability: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub(super) struct SierraParameter {
+    name: String,
+    r#type: String,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub(super) struct SierraOutput {
+    r#type: String,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum SierraEventKind {
+    Struct,
+    Enum,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum SierraMemberKind {
+    Key,
+    Data,
+    Nested,
+    Flat,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub(super) struct SierraEventMember {
+    name: String,
+    r#type: String,
+    kind: SierraMemberKind,
+}
+
+/// An event is either a struct of key and data members or an enum of other events. Events of
+/// compilers before Cairo 1.0 declare no kind and only data `inputs`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub(super) struct SierraEvent {
+    name: String,
+    #[serde(default)]
+    kind: Option<SierraEventKind>,
+    #[serde(default)]
+    members: Vec<SierraEventMember>,
+    #[serde(default)]
+    variants: Vec<SierraEventMember>,
+    #[serde(default)]
+    inputs: Vec<SierraParameter>,
+}
+
+impl SierraEvent {
+    fn is_enum(&self) -> bool {
+        self.kind == Some(SierraEventKind::Enum)
+    }
+}
+
+/// Converts parsed Sierra entries into the Cairo 0 model, together with warnings for the events
+/// which cannot be represented in it.
+///
+/// Contracts emit their events as variants of an enum, the selector in the first key is the
+/// name of the variant rather than of the struct the event is declared as, so events are named
+/// after the variants they are reached through. Structs which contain arrays have no fixed size
+/// and are left out.
+pub(super) fn convert(entries: Vec<SierraAbiEntry>) -> (Vec<ContractAbiEntry>, Vec<AbiWarning>) {
+    let entries = flatten(entries);
+    let structs: HashMap<&str, &[SierraParameter]> = entries
+        .iter()
+        .filter_map(|entry| match entry {
+            SierraAbiEntry::Struct { name, members } if name != U256 => {
+                Some((name.as_str(), members.as_slice()))
+            }
+            _ => None,
+        })
+        .collect();
+    let events = entries
+        .iter()
+        .filter_map(|entry| match entry {
+            SierraAbiEntry::Event(event) => Some(event),
+            _ => None,
+        })
+        .collect();
+    let types = Types { structs, events };
+
+    let mut converted: Vec<ContractAbiEntry> = entries
+        .iter()
+        .filter_map(|entry| match entry {
+            SierraAbiEntry::Function(function) => {
+                Some(types.function(FunctionAbiType::Function, function))
+            }
+            SierraAbiEntry::Constructor(function) => {
+                Some(types.function(FunctionAbiType::Constructor, function))
+            }
+            SierraAbiEntry::L1Handler(function) => {
+                Some(types.function(FunctionAbiType::L1Handler, function))
+            }
+            SierraAbiEntry::Struct { name, members } => types.r#struct(name, members),
+            SierraAbiEntry::Event(_)
+            | SierraAbiEntry::Interface { .. }
+            | SierraAbiEntry::Impl {}
+            | SierraAbiEntry::Enum {} => None,
+        })
+        .collect();
+    let mut warnings = Vec::new();
+    converted.extend(types.events(&mut warnings));
+
+    (converted, warnings)
+}
+
+/// Moves the functions of interfaces to the top level.
+fn flatten(entries: Vec<SierraAbiEntry>) -> Vec<SierraAbiEntry> {
+    let mut flat = Vec::with_capacity(entries.len());
+    for entry in entries {
+        match entry {
+            SierraAbiEntry::Interface { items } => flat.extend(flatten(items)),
+            entry => flat.push(entry),
+        }
+    }
+    flat
+}
+
+struct Types<'a> {
+    structs: HashMap<&'a str, &'a [SierraParameter]>,
+    events: Vec<&'a SierraEvent>,
+}
+
+impl Types<'_> {
+    fn function(&self, r#type: FunctionAbiType, function: &SierraFunction) -> ContractAbiEntry {
+        let outputs = function
+            .outputs
+            .iter()
+            .map(|output| SierraParameter {
+                name: String::new(),
+                r#type: output.r#type.clone(),
+            })
+            .collect::<Vec<_>>();
+
+        ContractAbiEntry::Function(FunctionAbiEntry {
+            r#type,
+            name: function.name.clone(),
+            inputs: Some(self.parameters(&function.inputs)),
+            outputs: Some(self.parameters(&outputs)),
+            state_mutability: function.state_mutability.clone(),
+        })
+    }
+
+    fn r#struct(&self, name: &str, members: &[SierraParameter]) -> Option<ContractAbiEntry> {
+        if name == U256 {
+            return None;
+        }
+
+        let mut offset = 0;
+        let mut struct_members = Vec::with_capacity(members.len());
+        for member in members {
+            let size = self.size(&member.r#type, 0)?;
+            struct_members.push(StructMember {
+                typed_parameter_name: member.name.clone(),
+                typed_parameter_type: self.r#type(&member.r#type),
+                offset,
+            });
+            offset += size;
+        }
+
+        Some(ContractAbiEntry::Struct(StructAbiEntry {
+            r#type: StructAbiType::Struct,
+            name: name.to_owned(),
+            size: offset,
+            members: struct_members,
+        }))
+    }
+
+    /// Converts the events reached through the enums which are no variant of another enum,
+    /// usually the `Event` enum of the contract. Struct events which no enum refers to are
+    /// named after the last segment of their path.
+    fn events(&self, warnings: &mut Vec<AbiWarning>) -> Vec<ContractAbiEntry> {
+        let variants: HashSet<&str> = self
+            .events
+            .iter()
+            .flat_map(|event| &event.variants)
+            .map(|variant| variant.r#type.as_str())
+            .collect();
+
+        let mut converted = Vec::new();
+        for event in &self.events {
+            if event.is_enum() && !variants.contains(event.name.as_str()) {
+                self.variants(event, 0, &mut converted, warnings);
+            }
+        }
+        for event in &self.events {
+            if !event.is_enum() && !variants.contains(event.name.as_str()) {
+                let name = event.name.rsplit("::").next().unwrap_or(&event.name);
+                converted.push(self.event(name, event));
+            }
+        }
+
+        converted
+    }
+
+    /// Converts the variants of an enum event. A `nested` variant prepends its name as the
+    /// selector, a `flat` one leaves it to the variants of the enum it wraps.
+    fn variants(
+        &self,
+        r#enum: &SierraEvent,
+        depth: usize,
+        converted: &mut Vec<ContractAbiEntry>,
+        warnings: &mut Vec<AbiWarning>,
+    ) {
+        for variant in &r#enum.variants {
+            let event = self
+                .events
+                .iter()
+                .find(|event| event.name == variant.r#type);
+            match (&variant.kind, event) {
+                (SierraMemberKind::Nested, Some(event)) if !event.is_enum() => {
+                    converted.push(self.event(&variant.name, event))
+                }
+                (SierraMemberKind::Flat, Some(event)) if event.is_enum() && depth < MAX_DEPTH => {
+                    self.variants(event, depth + 1, converted, warnings)
+                }
+                // A nested enum puts the selectors of both variants into the keys, and a flat
+                // struct none, neither of which an event is matched by.
+                _ => warnings.push(AbiWarning::UnsupportedEvent {
+                    name: format!("{}::{}", r#enum.name, variant.name),
+                }),
+            }
+        }
+    }
+
+    fn event(&self, name: &str, event: &SierraEvent) -> ContractAbiEntry {
+        let (keys, data) = match event.kind {
+            Some(SierraEventKind::Struct) => {
+                let members = |kind: SierraMemberKind| {
+                    event
+                        .members
+                        .iter()
+                        .filter(|member| member.kind == kind)
+                        .map(|member| SierraParameter {
+                            name: member.name.clone(),
+                            r#type: member.r#type.clone(),
+                        })
+                        .collect::<Vec<_>>()
+                };
+                (
+                    self.parameters(&members(SierraMemberKind::Key)),
+                    self.parameters(&members(SierraMemberKind::Data)),
+                )
+            }
+            _ => (Vec::new(), self.parameters(&event.inputs)),
+        };
+
+        ContractAbiEntry::Event(EventAbiEntry {
+            r#type: EventAbiType::Event,
+            name: name.to_owned(),
+            keys: Some(keys),
+            data: Some(data),
+            inputs: None,
+            outputs: None,
+        })
+    }
+
+    /// Converts parameters, expanding arrays into a length and a pointer.
+    fn parameters(&self, parameters: &[SierraParameter]) -> Vec<TypedParameter> {
+        let mut converted = Vec::with_capacity(parameters.len());
+        for parameter in parameters {
+            match array_element(&parameter.r#type) {
+                Some(element) => {
+                    converted.push(TypedParameter {
+                        name: format!("{}_len", parameter.name),
+                        r#type: "felt".to_owned(),
+                    });
+                    converted.push(TypedParameter {
+                        name: parameter.name.clone(),
+                        r#type: format!("{}*", self.r#type(element)),
+                    });
+                }
+                None => converted.push(TypedParameter {
+                    name: parameter.name.clone(),
+                    r#type: self.r#type(&parameter.r#type),
+                }),
+            }
+        }
+        converted
+    }
+
+    /// The Cairo 0 name of a type, types without one keep their Sierra name. Generic types
+    /// other than arrays are not Cairo 0 syntax and are kept as a whole.
+    fn r#type(&self, r#type: &str) -> String {
+        match CairoType::parse(r#type) {
+            Ok(parsed) => cairo_type(parsed).to_string(),
+            Err(_) => r#type.to_owned(),
+        }
+    }
+
+    /// Number of felts a value of the type occupies, `None` if it is not fixed.
+    fn size(&self, r#type: &str, depth: usize) -> Option<u64> {
+        self.cairo_type_size(&cairo_type(CairoType::parse(r#type).ok()?), depth)
+    }
+
+    fn cairo_type_size(&self, r#type: &CairoType, depth: usize) -> Option<u64> {
+        match r#type {
+            CairoType::Felt => Some(1),
+            CairoType::Named(name) if name == "Uint256" => Some(2),
+            CairoType::Tuple(members) => members.iter().try_fold(0u64, |size, member| {
+                size.checked_add(self.cairo_type_size(&member.r#type, depth)?)
+            }),
+            CairoType::Named(name) if depth < MAX_DEPTH => self
+                .structs
+                .get(name.as_str())?
+                .iter()
+                .try_fold(0u64, |size, member| {
+                    size.checked_add(self.size(&member.r#type, depth + 1)?)
+                }),
+            // Arrays are only known as members, not within other types.
+            CairoType::Named(_) | CairoType::Pointer(_) => None,
+        }
+    }
+}
+
+/// Replaces the Sierra names of felt sized primitives and `u256` within a parsed type.
+fn cairo_type(r#type: CairoType) -> CairoType {
+    match r#type {
+        CairoType::Named(name) if name == U256 => CairoType::Named("Uint256".to_owned()),
+        CairoType::Named(name) if FELT_TYPES.contains(&name.as_str()) => CairoType::Felt,
+        CairoType::Tuple(members) => CairoType::Tuple(
+            members
+                .into_iter()
+                .map(|member| TupleMember {
+                    r#type: cairo_type(member.r#type),
+                    ..member
+                })
+                .collect(),
+        ),
+        CairoType::Pointer(r#type) => CairoType::Pointer(Box::new(cairo_type(*r#type))),
+        r#type => r#type,
+    }
+}
+
+/// The element type of a `core::array::Array` or `core::array::Span`.
+fn array_element(r#type: &str) -> Option<&str> {
+    ["core::array::Array::<", "core::array::Span::<"]
+        .iter()
+        .find_map(|prefix| r#type.strip_prefix(prefix))
+        .and_then(|element| element.strip_suffix('>'))
+}
+
+#[cfg(test)]
+mod tests {
+    use pathfinder_common::{EventData, EventKey};
+    use stark_hash::Felt;
+
+    use super::*;
+    use crate::class::decode::{AbiDecoder, DecodedMember, DecodedValue};
+
+    #[test]
+    fn events_are_split_into_keys_and_data() {
+        let entries: Vec<SierraAbiEntry> = serde_json::from_str(
+            r#"[
+                {
+                    "type": "event",
+                    "name": "openzeppelin::token::erc1155::erc1155::ERC1155Component::TransferBatch",
+                    "kind": "struct",
+                    "members": [
+                        {"name": "operator", "type": "core::starknet::contract_address::ContractAddress", "kind": "key"},
+                        {"name": "from", "type": "core::starknet::contract_address::ContractAddress", "kind": "key"},
+                        {"name": "to", "type": "core::starknet::contract_address::ContractAddress", "kind": "key"},
+                        {"name": "ids", "type": "core::array::Span::<core::integer::u256>", "kind": "data"},
+                        {"name": "values", "type": "core::array::Span::<core::integer::u256>", "kind": "data"}
+                    ]
+                },
+                {
+                    "type": "event",
+                    "name": "openzeppelin::token::erc1155::erc1155::ERC1155Component::Event",
+                    "kind": "enum",
+                    "variants": [
+                        {"name": "TransferBatch", "type": "openzeppelin::token::erc1155::erc1155::ERC1155Component::TransferBatch", "kind": "nested"}
+                    ]
+                }
+            ]"#,
+        )
+        .unwrap();
+
+        let parameter = |name: &str, r#type: &str| TypedParameter {
+            name: name.to_owned(),
+            r#type: r#type.to_owned(),
+        };
+        assert_eq!(
+            convert(entries).0,
+            vec![ContractAbiEntry::Event(EventAbiEntry {
+                r#type: EventAbiType::Event,
+                name: "TransferBatch".to_owned(),
+                keys: Some(vec![
+                    parameter("operator", "felt"),
+                    parameter("from", "felt"),
+                    parameter("to", "felt"),
+                ]),
+                data: Some(vec![
+                    parameter("ids_len", "felt"),
+                    parameter("ids", "Uint256*"),
+                    parameter("values_len", "felt"),
+                    parameter("values", "Uint256*"),
+                ]),
+                inputs: None,
+                outputs: None,
+            })]
+        );
+    }
+
+    #[test]
+    fn events_are_named_after_their_variants() {
+        let entries: Vec<SierraAbiEntry> = serde_json::from_str(
+            r#"[
+                {
+                    "type": "event",
+                    "name": "openzeppelin::token::erc721::erc721::ERC721Component::Transfer",
+                    "kind": "struct",
+                    "members": [
+                        {"name": "from", "type": "core::starknet::contract_address::ContractAddress", "kind": "key"},
+                        {"name": "to", "type": "core::starknet::contract_address::ContractAddress", "kind": "key"},
+                        {"name": "token_id", "type": "core::integer::u256", "kind": "key"}
+                    ]
+                },
+                {
+                    "type": "event",
+                    "name": "openzeppelin::token::erc721::erc721::ERC721Component::Event",
+                    "kind": "enum",
+                    "variants": [
+                        {"name": "Transfer", "type": "openzeppelin::token::erc721::erc721::ERC721Component::Transfer", "kind": "nested"}
+                    ]
+                },
+                {
+                    "type": "event",
+                    "name": "openzeppelin::upgrades::upgradeable::UpgradeableComponent::Upgraded",
+                    "kind": "struct",
+                    "members": [
+                        {"name": "class_hash", "type": "core::starknet::class_hash::ClassHash", "kind": "data"}
+                    ]
+                },
+                {
+                    "type": "event",
+                    "name": "openzeppelin::upgrades::upgradeable::UpgradeableComponent::Event",
+                    "kind": "enum",
+                    "variants": [
+                        {"name": "Upgraded", "type": "openzeppelin::upgrades::upgradeable::UpgradeableComponent::Upgraded", "kind": "nested"}
+                    ]
+                },
+                {
+                    "type": "event",
+                    "name": "collection::NameChanged",
+                    "kind": "struct",
+                    "members": [
+                        {"name": "name", "type": "core::felt252", "kind": "data"}
+                    ]
+                },
+                {
+                    "type": "event",
+                    "name": "collection::Collection::Event",
+                    "kind": "enum",
+                    "variants": [
+                        {"name": "ERC721Event", "type": "openzeppelin::token::erc721::erc721::ERC721Component::Event", "kind": "flat"},
+                        {"name": "UpgradeableEvent", "type": "openzeppelin::upgrades::upgradeable::UpgradeableComponent::Event", "kind": "nested"},
+                        {"name": "Renamed", "type": "collection::NameChanged", "kind": "nested"}
+                    ]
+                }
+            ]"#,
+        )
+        .unwrap();
+
+        let (converted, warnings) = convert(entries);
+
+        let names = converted
+            .iter()
+            .map(|entry| match entry {
+                ContractAbiEntry::Event(event) => event.name.as_str(),
+                _ => panic!("not an event: {entry:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["Transfer", "Renamed"]);
+        assert_eq!(
+            warnings,
+            [AbiWarning::UnsupportedEvent {
+                name: "collection::Collection::Event::UpgradeableEvent".to_owned()
+            }]
+        );
+    }
+
+    #[test]
+    fn tuple_members_are_converted() {
+        let entries: Vec<SierraAbiEntry> = serde_json::from_str(
+            r#"[
+                {
+                    "type": "struct",
+                    "name": "game::Move",
+                    "members": [
+                        {"name": "from", "type": "(core::integer::u8, core::integer::u8)"},
+                        {"name": "to", "type": "(core::integer::u8, core::integer::u8)"}
+                    ]
+                },
+                {
+                    "type": "event",
+                    "name": "game::Moved",
+                    "kind": "struct",
+                    "members": [
+                        {"name": "player", "type": "core::starknet::contract_address::ContractAddress", "kind": "key"},
+                        {"name": "pos", "type": "(core::felt252, core::felt252)", "kind": "data"},
+                        {"name": "moves", "type": "core::array::Span::<game::Move>", "kind": "data"}
+                    ]
+                }
+            ]"#,
+        )
+        .unwrap();
+
+        let (abi, warnings) = convert(entries);
+        assert!(warnings.is_empty());
+        let event = abi
+            .iter()
+            .find_map(|entry| match entry {
+                ContractAbiEntry::Event(event) => Some(event),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(event.data.as_deref().unwrap()[0].r#type, "(felt, felt)");
+
+        // selector and player, then pos, a single move from (1, 2) to (3, 4)
+        let keys = [
+            EventKey(Felt::from_u64(0x5e1)),
+            EventKey(Felt::from_u64(0xa)),
+        ];
+        let data = [5, 6, 1, 1, 2, 3, 4]
+            .iter()
+            .map(|felt| EventData(Felt::from_u64(*felt)))
+            .collect::<Vec<_>>();
+        let decoded = AbiDecoder::new(&abi).decode(event, &keys, &data).unwrap();
+
+        let felts = |felts: &[u64]| {
+            felts
+                .iter()
+                .map(|felt| DecodedValue::Felt(Felt::from_u64(*felt)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            decoded.get("pos"),
+            Some(&DecodedValue::Tuple(felts(&[5, 6])))
+        );
+        assert_eq!(
+            decoded.get("moves"),
+            Some(&DecodedValue::Array(vec![DecodedValue::Struct(vec![
+                DecodedMember {
+                    name: "from".to_owned(),
+                    value: DecodedValue::Tuple(felts(&[1, 2])),
+                },
+                DecodedMember {
+                    name: "to".to_owned(),
+                    value: DecodedValue::Tuple(felts(&[3, 4])),
+                },
+            ])]))
+        );
+    }
+
+    #[test]
+    fn interfaces_and_structs() {
+        let entries: Vec<SierraAbiEntry> = serde_json::from_str(
+            r#"[
+                {"type": "impl", "name": "ERC721Impl", "interface_name": "IERC721"},
+                {
+                    "type": "interface",
+                    "name": "IERC721",
+                    "items": [{
+                        "type": "function",
+                        "name": "owner_of",
+                        "inputs": [{"name": "token_id", "type": "core::integer::u256"}],
+                        "outputs": [{"type": "core::starknet::contract_address::ContractAddress"}],
+                        "state_mutability": "view"
+                    }]
+                },
+                {
+                    "type": "struct",
+                    "name": "core::integer::u256",
+                    "members": [
+                        {"name": "low", "type": "core::integer::u128"},
+                        {"name": "high", "type": "core::integer::u128"}
+                    ]
+                },
+                {
+                    "type": "struct",
+                    "name": "market::Listing",
+                    "members": [
+                        {"name": "seller", "type": "core::starknet::contract_address::ContractAddress"},
+                        {"name": "price", "type": "core::integer::u256"}
+                    ]
+                },
+                {
+                    "type": "struct",
+                    "name": "market::Bundle",
+                    "members": [
+                        {"name": "listing", "type": "market::Listing"},
+                        {"name": "items", "type": "core::array::Array::<core::felt252>"}
+                    ]
+                }
+            ]"#,
+        )
+        .unwrap();
+
+        let (converted, _) = convert(entries);
+
+        assert_eq!(converted.len(), 2);
+        assert!(matches!(
+            &converted[0],
+            ContractAbiEntry::Function(function)
+                if function.name == "owner_of"
+                    && function.inputs.as_deref().unwrap()[0].r#type == "Uint256"
+        ));
+        assert!(matches!(
+            &converted[1],
+            ContractAbiEntry::Struct(listing)
+                if listing.name == "market::Listing" && listing.size == 3
+        ));
+    }
+}
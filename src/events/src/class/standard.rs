@@ -0,0 +1,223 @@
+//! Detection of the token standard a class implements from the functions and events of its ABI.
+use super::{ContractAbiEntry, EventAbiEntry, FunctionAbiEntry};
+
+const UINT256: &str = "Uint256";
+
+/// Functions which only an ERC-1155 collection declares, Cairo 1 classes use snake case names.
+const ERC1155_FUNCTIONS: &[&str] = &[
+    "safeBatchTransferFrom",
+    "balanceOfBatch",
+    "safe_batch_transfer_from",
+    "balance_of_batch",
+];
+/// Functions which only an ERC-721 collection declares.
+const ERC721_FUNCTIONS: &[&str] = &["ownerOf", "getApproved", "owner_of", "get_approved"];
+/// Functions which only an ERC-20 token declares.
+const ERC20_FUNCTIONS: &[&str] = &["decimals", "allowance"];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Standard {
+    ERC20,
+    ERC721,
+    ERC1155,
+    Unknown,
+}
+
+/// The evidence a [Standard] was chosen on, from strongest to weakest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Reason {
+    /// The class declares these functions, which are specific to the standard.
+    Functions(Vec<&'static str>),
+    /// The class declares none of the specific functions, but its events have the shape of the
+    /// standard.
+    Events,
+    /// Neither the functions nor the events match a standard.
+    NoMatch,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Classification {
+    pub standard: Standard,
+    pub reason: Reason,
+}
+
+impl Classification {
+    /// Classifies a class by the functions of its ABI, falling back to the shape of its events.
+    pub fn from_abi(abi: &[ContractAbiEntry]) -> Self {
+        let functions: Vec<&FunctionAbiEntry> = abi
+            .iter()
+            .filter_map(|entry| match entry {
+                ContractAbiEntry::Function(function) => Some(function),
+                _ => None,
+            })
+            .collect();
+        let declared = |names: &[&'static str]| -> Vec<&'static str> {
+            names
+                .iter()
+                .copied()
+                .filter(|name| functions.iter().any(|function| function.name == *name))
+                .collect()
+        };
+
+        for (standard, names) in [
+            (Standard::ERC1155, ERC1155_FUNCTIONS),
+            (Standard::ERC721, ERC721_FUNCTIONS),
+            (Standard::ERC20, ERC20_FUNCTIONS),
+        ] {
+            let matched = declared(names);
+            if !matched.is_empty() {
+                return Self {
+                    standard,
+                    reason: Reason::Functions(matched),
+                };
+            }
+        }
+
+        let introspects = functions.iter().any(|function| {
+            function.name == "supportsInterface" || function.name == "supports_interface"
+        });
+        let standard = Self::standard_from_events(abi, introspects);
+        let reason = match standard {
+            Standard::Unknown => Reason::NoMatch,
+            _ => Reason::Events,
+        };
+
+        Self { standard, reason }
+    }
+
+    /// Both NFT standards declare `ApprovalForAll`, ERC-1155 is told apart by its
+    /// `TransferSingle` event. A fungible token declares `Transfer` and `Approval` events which
+    /// carry a `Uint256` amount, but no ERC-165 `supportsInterface` as the NFT standards do.
+    fn standard_from_events(abi: &[ContractAbiEntry], introspects: bool) -> Standard {
+        let event = |name: &str| {
+            abi.iter().find_map(|entry| match entry {
+                ContractAbiEntry::Event(event) if event.name == name => Some(event),
+                _ => None,
+            })
+        };
+        let carries_amount = |event: Option<&EventAbiEntry>| {
+            event
+                .and_then(|event| event.data.as_deref())
+                .is_some_and(|members| {
+                    matches!(members, [from, to, amount]
+                        if from.r#type == "felt" && to.r#type == "felt" && amount.r#type == UINT256)
+                })
+        };
+
+        if event("TransferSingle").is_some() || event("TransferBatch").is_some() {
+            Standard::ERC1155
+        } else if event("ApprovalForAll").is_some() {
+            Standard::ERC721
+        } else if !introspects
+            && carries_amount(event("Transfer"))
+            && carries_amount(event("Approval"))
+        {
+            Standard::ERC20
+        } else {
+            Standard::Unknown
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(abi: &str) -> Classification {
+        Classification::from_abi(&serde_json::from_str::<Vec<ContractAbiEntry>>(abi).unwrap())
+    }
+
+    #[test]
+    fn functions_take_precedence_over_events() {
+        // The token id member is neither called `_tokenId` nor accompanied by `ApprovalForAll`.
+        let classification = classify(
+            r#"[
+                {
+                    "type": "function",
+                    "name": "ownerOf",
+                    "inputs": [{"name": "token_id", "type": "Uint256"}],
+                    "outputs": [{"name": "owner", "type": "felt"}],
+                    "stateMutability": "view"
+                },
+                {
+                    "type": "event",
+                    "name": "Transfer",
+                    "keys": [],
+                    "data": [
+                        {"name": "from_", "type": "felt"},
+                        {"name": "to", "type": "felt"},
+                        {"name": "token_id", "type": "Uint256"}
+                    ]
+                }
+            ]"#,
+        );
+
+        assert_eq!(
+            classification,
+            Classification {
+                standard: Standard::ERC721,
+                reason: Reason::Functions(vec!["ownerOf"]),
+            }
+        );
+    }
+
+    #[test]
+    fn falls_back_to_events() {
+        let classification = classify(
+            r#"[
+                {
+                    "type": "event",
+                    "name": "Transfer",
+                    "keys": [],
+                    "data": [
+                        {"name": "from_", "type": "felt"},
+                        {"name": "to", "type": "felt"},
+                        {"name": "value", "type": "Uint256"}
+                    ]
+                },
+                {
+                    "type": "event",
+                    "name": "Approval",
+                    "keys": [],
+                    "data": [
+                        {"name": "owner", "type": "felt"},
+                        {"name": "spender", "type": "felt"},
+                        {"name": "value", "type": "Uint256"}
+                    ]
+                }
+            ]"#,
+        );
+
+        assert_eq!(
+            classification,
+            Classification {
+                standard: Standard::ERC20,
+                reason: Reason::Events,
+            }
+        );
+    }
+
+    #[test]
+    fn unknown() {
+        let classification = classify(
+            r#"[{
+                "type": "function",
+                "name": "__default__",
+                "inputs": [
+                    {"name": "selector", "type": "felt"},
+                    {"name": "calldata_size", "type": "felt"},
+                    {"name": "calldata", "type": "felt*"}
+                ],
+                "outputs": []
+            }]"#,
+        );
+
+        assert_eq!(
+            classification,
+            Classification {
+                standard: Standard::Unknown,
+                reason: Reason::NoMatch,
+            }
+        );
+    }
+}
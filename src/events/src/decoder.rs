@@ -0,0 +1,113 @@
+//! Decoders turning raw events into [IndexedEvent]s, dispatched through a [DecoderRegistry] by
+//! the selector of the event and the ABI of the contract emitting it.
+mod erc1155;
+mod erc20;
+mod erc721;
+mod token;
+
+use std::collections::HashMap;
+
+use pathfinder_common::{ClassHash, EntryPoint, EventData, EventKey};
+use serde::Serialize;
+
+use crate::class::decode::{AbiDecoder, DecodeError, DecodedEvent};
+use crate::class::EventAbiEntry;
+use crate::{ClassAbi, EmittedAt, GetEventsError, StarknetEmittedEvent};
+
+pub use erc1155::Erc1155Decoder;
+pub use erc20::Erc20Decoder;
+pub use erc721::Erc721Decoder;
+
+/// An event produced by an [EventDecoder].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum IndexedEvent {
+    /// A transfer or approval of a token standard.
+    Token(StarknetEmittedEvent),
+    /// Any other event, stored as `document` in `collection`.
+    Custom {
+        collection: String,
+        document: bson::Document,
+    },
+}
+
+/// An event as read from the database, together with its ABI definition.
+pub struct RawEvent<'a> {
+    pub abi: &'a ClassAbi,
+    /// The class of the emitting contract.
+    pub class_hash: ClassHash,
+    /// The definition of the event in `abi`, found by its selector.
+    pub definition: &'a EventAbiEntry,
+    /// All keys of the event, starting with its selector.
+    pub keys: &'a [EventKey],
+    pub data: &'a [EventData],
+    pub emitted: &'a EmittedAt,
+}
+
+impl RawEvent<'_> {
+    /// Decodes the members of the event according to its definition, key members first.
+    pub fn decode(&self) -> Result<DecodedEvent, DecodeError> {
+        AbiDecoder::new(&self.abi.abi).decode(self.definition, self.keys, self.data)
+    }
+}
+
+pub trait EventDecoder: Send + Sync {
+    /// Names of the events this decoder handles, their selectors are matched against the first
+    /// key of an event.
+    fn event_names(&self) -> Vec<&str>;
+
+    /// Whether the events of a contract with this ABI are handled by this decoder.
+    fn applies_to(&self, abi: &ClassAbi) -> bool;
+
+    /// Decodes an event, which can produce any number of [IndexedEvent]s.
+    fn decode(&self, event: &RawEvent<'_>) -> Result<Vec<IndexedEvent>, GetEventsError>;
+}
+
+/// The decoders of a scan, by selector.
+///
+/// When several decoders handle the same selector, the first one registered which applies to
+/// the ABI of the emitting contract decodes the event.
+pub struct DecoderRegistry {
+    decoders: Vec<Box<dyn EventDecoder>>,
+    selectors: HashMap<EventKey, Vec<usize>>,
+}
+
+impl DecoderRegistry {
+    /// Creates a registry without any decoders.
+    pub fn empty() -> Self {
+        Self {
+            decoders: Vec::new(),
+            selectors: HashMap::new(),
+        }
+    }
+
+    pub fn register(&mut self, decoder: impl EventDecoder + 'static) -> &mut Self {
+        let index = self.decoders.len();
+        for name in decoder.event_names() {
+            let selector = EventKey(EntryPoint::hashed(name.as_bytes()).0);
+            self.selectors.entry(selector).or_default().push(index);
+        }
+        self.decoders.push(Box::new(decoder));
+        self
+    }
+
+    /// Returns the decoder for events with `selector` emitted by a contract with this ABI.
+    pub fn find(&self, selector: &EventKey, abi: &ClassAbi) -> Option<&dyn EventDecoder> {
+        self.selectors
+            .get(selector)?
+            .iter()
+            .map(|index| self.decoders[*index].as_ref())
+            .find(|decoder| decoder.applies_to(abi))
+    }
+}
+
+/// A registry with the decoders of the ERC-20, ERC-721 and ERC-1155 standards.
+impl Default for DecoderRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry
+            .register(Erc20Decoder)
+            .register(Erc721Decoder)
+            .register(Erc1155Decoder);
+        registry
+    }
+}
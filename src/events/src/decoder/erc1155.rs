@@ -0,0 +1,74 @@
+use pathfinder_serde::u256_to_dec_str;
+
+use super::{token, EventDecoder, IndexedEvent, RawEvent};
+use crate::{ClassAbi, ContractType, GetEventsError, StarknetEmittedEvent};
+
+/// Decodes `TransferSingle`, `TransferBatch` and `ApprovalForAll` events of multi token
+/// collections. A `TransferBatch` is fanned out into one event per transferred id.
+pub struct Erc1155Decoder;
+
+impl EventDecoder for Erc1155Decoder {
+    fn event_names(&self) -> Vec<&str> {
+        vec!["TransferSingle", "TransferBatch", "ApprovalForAll"]
+    }
+
+    fn applies_to(&self, abi: &ClassAbi) -> bool {
+        token::contract_type(abi) == Some(ContractType::ERC1155)
+    }
+
+    fn decode(&self, event: &RawEvent<'_>) -> Result<Vec<IndexedEvent>, GetEventsError> {
+        let members = event.decode()?;
+        let decoded = match event.definition.name.as_str() {
+            "TransferSingle" => {
+                // TransferSingle(operator, from_, to, id: Uint256, value: Uint256)
+                let operator = token::felt_at(&members, 0)?;
+                let from = token::felt_at(&members, 1)?;
+                let to = token::felt_at(&members, 2)?;
+                let (token_id, amount) = token::decode_transfer_single(&members)?;
+                vec![StarknetEmittedEvent {
+                    operator: Some(operator.to_string()),
+                    from: from.to_string(),
+                    to: to.to_string(),
+                    token_id: u256_to_dec_str(&token_id),
+                    amount: u256_to_dec_str(&amount),
+                    ..StarknetEmittedEvent::new(
+                        event.emitted,
+                        token::event_type(from, to),
+                        ContractType::ERC1155,
+                    )
+                }]
+            }
+            "TransferBatch" => {
+                let items = token::decode_transfer_batch(&members)?;
+                let operator = token::felt_at(&members, 0)?;
+                let from = token::felt_at(&members, 1)?;
+                let to = token::felt_at(&members, 2)?;
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (token_id, amount))| StarknetEmittedEvent {
+                        operator: Some(operator.to_string()),
+                        from: from.to_string(),
+                        to: to.to_string(),
+                        token_id: u256_to_dec_str(token_id),
+                        amount: u256_to_dec_str(amount),
+                        batch_index: Some(index as u64),
+                        ..StarknetEmittedEvent::new(
+                            event.emitted,
+                            token::event_type(from, to),
+                            ContractType::ERC1155,
+                        )
+                    })
+                    .collect()
+            }
+            "ApprovalForAll" => vec![token::approval_for_all(
+                &members,
+                event.emitted,
+                ContractType::ERC1155,
+            )?],
+            _ => Vec::new(),
+        };
+
+        Ok(decoded.into_iter().map(IndexedEvent::Token).collect())
+    }
+}
@@ -0,0 +1,58 @@
+use pathfinder_serde::u256_to_dec_str;
+
+use super::{token, EventDecoder, IndexedEvent, RawEvent};
+use crate::{ClassAbi, ContractType, EventType, GetEventsError, StarknetEmittedEvent};
+
+/// Decodes `Transfer` and `Approval` events of fungible tokens.
+pub struct Erc20Decoder;
+
+impl EventDecoder for Erc20Decoder {
+    fn event_names(&self) -> Vec<&str> {
+        vec!["Transfer", "Approval"]
+    }
+
+    fn applies_to(&self, abi: &ClassAbi) -> bool {
+        token::contract_type(abi) == Some(ContractType::ERC20)
+    }
+
+    fn decode(&self, event: &RawEvent<'_>) -> Result<Vec<IndexedEvent>, GetEventsError> {
+        let members = event.decode()?;
+        let decoded = match event.definition.name.as_str() {
+            "Transfer" => {
+                // Transfer(from_, to, value: Uint256)
+                let from = token::felt_at(&members, 0)?;
+                let to = token::felt_at(&members, 1)?;
+                let amount = token::uint_at(&members, 2)?;
+                StarknetEmittedEvent {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                    amount: u256_to_dec_str(&amount),
+                    ..StarknetEmittedEvent::new(
+                        event.emitted,
+                        token::event_type(from, to),
+                        ContractType::ERC20,
+                    )
+                }
+            }
+            "Approval" => {
+                // Approval(owner, spender, value: Uint256)
+                let owner = token::felt_at(&members, 0)?;
+                let spender = token::felt_at(&members, 1)?;
+                let amount = token::uint_at(&members, 2)?;
+                StarknetEmittedEvent {
+                    owner: Some(owner.to_string()),
+                    operator: Some(spender.to_string()),
+                    amount: u256_to_dec_str(&amount),
+                    ..StarknetEmittedEvent::new(
+                        event.emitted,
+                        EventType::Approval,
+                        ContractType::ERC20,
+                    )
+                }
+            }
+            _ => return Ok(Vec::new()),
+        };
+
+        Ok(vec![IndexedEvent::Token(decoded)])
+    }
+}
@@ -0,0 +1,62 @@
+use pathfinder_serde::u256_to_dec_str;
+
+use super::{token, EventDecoder, IndexedEvent, RawEvent};
+use crate::{ClassAbi, ContractType, EventType, GetEventsError, StarknetEmittedEvent};
+
+/// Decodes `Transfer`, `Approval` and `ApprovalForAll` events of NFT collections.
+pub struct Erc721Decoder;
+
+impl EventDecoder for Erc721Decoder {
+    fn event_names(&self) -> Vec<&str> {
+        vec!["Transfer", "Approval", "ApprovalForAll"]
+    }
+
+    fn applies_to(&self, abi: &ClassAbi) -> bool {
+        token::contract_type(abi) == Some(ContractType::ERC721)
+    }
+
+    fn decode(&self, event: &RawEvent<'_>) -> Result<Vec<IndexedEvent>, GetEventsError> {
+        let members = event.decode()?;
+        let decoded = match event.definition.name.as_str() {
+            "Transfer" => {
+                // Transfer(from_, to, tokenId)
+                let from = token::felt_at(&members, 0)?;
+                let to = token::felt_at(&members, 1)?;
+                let token_id = token::uint_at(&members, 2)?;
+                StarknetEmittedEvent {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                    token_id: u256_to_dec_str(&token_id),
+                    amount: "1".to_string(),
+                    ..StarknetEmittedEvent::new(
+                        event.emitted,
+                        token::event_type(from, to),
+                        ContractType::ERC721,
+                    )
+                }
+            }
+            "Approval" => {
+                // Approval(owner, approved, tokenId: Uint256)
+                let owner = token::felt_at(&members, 0)?;
+                let approved = token::felt_at(&members, 1)?;
+                let token_id = token::uint_at(&members, 2)?;
+                StarknetEmittedEvent {
+                    owner: Some(owner.to_string()),
+                    operator: Some(approved.to_string()),
+                    token_id: u256_to_dec_str(&token_id),
+                    ..StarknetEmittedEvent::new(
+                        event.emitted,
+                        EventType::Approval,
+                        ContractType::ERC721,
+                    )
+                }
+            }
+            "ApprovalForAll" => {
+                token::approval_for_all(&members, event.emitted, ContractType::ERC721)?
+            }
+            _ => return Ok(Vec::new()),
+        };
+
+        Ok(vec![IndexedEvent::Token(decoded)])
+    }
+}
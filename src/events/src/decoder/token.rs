@@ -0,0 +1,238 @@
+//! Decoding helpers shared by the decoders of the token standards.
+//!
+//! The standards fix the order of the members of their events but not their names or whether
+//! integers are felts or `Uint256`s, so members are read by position from the [DecodedEvent].
+use pathfinder_common::U256;
+use stark_hash::Felt;
+
+use crate::class::decode::{DecodedEvent, DecodedValue};
+use crate::class::standard::Standard;
+use crate::{ClassAbi, ContractType, EmittedAt, EventDecodeError, EventType, StarknetEmittedEvent};
+
+/// The [ContractType] of a class, `None` when it implements no known token standard.
+pub(super) fn contract_type(abi: &ClassAbi) -> Option<ContractType> {
+    match abi.classification.standard {
+        Standard::ERC20 => Some(ContractType::ERC20),
+        Standard::ERC721 => Some(ContractType::ERC721),
+        Standard::ERC1155 => Some(ContractType::ERC1155),
+        Standard::Unknown => None,
+    }
+}
+
+/// Classifies a transfer as a mint or burn when it moves from or to the zero address.
+pub(super) fn event_type(from: &Felt, to: &Felt) -> EventType {
+    if from.is_zero() {
+        EventType::Mint
+    } else if to.is_zero() {
+        EventType::Burn
+    } else {
+        EventType::Transfer
+    }
+}
+
+/// Decodes the `(id, value)` pair of a
+/// `TransferSingle(operator, from_, to, id: Uint256, value: Uint256)` event.
+pub(super) fn decode_transfer_single(
+    event: &DecodedEvent,
+) -> Result<(U256, U256), EventDecodeError> {
+    Ok((uint_at(event, 3)?, uint_at(event, 4)?))
+}
+
+/// Decodes the `(id, value)` pairs of a
+/// `TransferBatch(operator, from_, to, ids_len, ids: Uint256*, values_len, values: Uint256*)`
+/// event.
+pub(super) fn decode_transfer_batch(
+    event: &DecodedEvent,
+) -> Result<Vec<(U256, U256)>, EventDecodeError> {
+    let ids = uint_array_at(event, 4)?;
+    let values = uint_array_at(event, 6)?;
+
+    if ids.len() != values.len() {
+        return Err(EventDecodeError::ArrayLengthMismatch {
+            ids: ids.len(),
+            values: values.len(),
+        });
+    }
+
+    Ok(ids.into_iter().zip(values).collect())
+}
+
+/// Returns the value of the member at `index`.
+fn member_at(event: &DecodedEvent, index: usize) -> Result<&DecodedValue, EventDecodeError> {
+    event
+        .members
+        .get(index)
+        .map(|member| &member.value)
+        .ok_or(EventDecodeError::MissingMember {
+            expected: index + 1,
+            actual: event.members.len(),
+        })
+}
+
+/// Returns the felt held by the member at `index`.
+pub(super) fn felt_at(event: &DecodedEvent, index: usize) -> Result<&Felt, EventDecodeError> {
+    match member_at(event, index)? {
+        DecodedValue::Felt(felt) => Ok(felt),
+        _ => Err(EventDecodeError::UnexpectedType {
+            index,
+            expected: "felt",
+        }),
+    }
+}
+
+/// Returns the unsigned integer held by the member at `index`, declared as a felt or as a
+/// `Uint256`.
+pub(super) fn uint_at(event: &DecodedEvent, index: usize) -> Result<U256, EventDecodeError> {
+    uint(member_at(event, index)?).ok_or(EventDecodeError::UnexpectedType {
+        index,
+        expected: "an unsigned integer",
+    })
+}
+
+/// Returns the unsigned integers of the array member at `index`.
+fn uint_array_at(event: &DecodedEvent, index: usize) -> Result<Vec<U256>, EventDecodeError> {
+    let error = EventDecodeError::UnexpectedType {
+        index,
+        expected: "an array of unsigned integers",
+    };
+    match member_at(event, index)? {
+        DecodedValue::Array(elements) => elements.iter().map(uint).collect::<Option<_>>(),
+        _ => None,
+    }
+    .ok_or(error)
+}
+
+fn uint(value: &DecodedValue) -> Option<U256> {
+    match value {
+        DecodedValue::Felt(felt) => Some(U256::from(*felt)),
+        DecodedValue::Uint256(value) => Some(*value),
+        _ => None,
+    }
+}
+
+/// Decodes `ApprovalForAll(owner, operator, approved: felt)`, which both NFT standards
+/// declare.
+pub(super) fn approval_for_all(
+    event: &DecodedEvent,
+    emitted: &EmittedAt,
+    contract_type: ContractType,
+) -> Result<StarknetEmittedEvent, EventDecodeError> {
+    let owner = felt_at(event, 0)?;
+    let operator = felt_at(event, 1)?;
+    let approved = !felt_at(event, 2)?.is_zero();
+
+    Ok(StarknetEmittedEvent {
+        owner: Some(owner.to_string()),
+        operator: Some(operator.to_string()),
+        approved: Some(approved),
+        ..StarknetEmittedEvent::new(emitted, EventType::ApprovalForAll, contract_type)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pathfinder_common::EventData;
+
+    use super::*;
+    use crate::class::decode::{AbiDecoder, DecodeError};
+    use crate::class::EventAbiEntry;
+
+    fn transfer_batch(felts: &[u64]) -> Result<DecodedEvent, DecodeError> {
+        let definition: EventAbiEntry = serde_json::from_str(
+            r#"{
+                "type": "event",
+                "name": "TransferBatch",
+                "keys": [],
+                "data": [
+                    {"name": "operator", "type": "felt"},
+                    {"name": "from_", "type": "felt"},
+                    {"name": "to", "type": "felt"},
+                    {"name": "ids_len", "type": "felt"},
+                    {"name": "ids", "type": "Uint256*"},
+                    {"name": "values_len", "type": "felt"},
+                    {"name": "values", "type": "Uint256*"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let data = felts
+            .iter()
+            .map(|f| EventData(Felt::from_u64(*f)))
+            .collect::<Vec<_>>();
+
+        AbiDecoder::with_structs([]).decode(&definition, &[], &data)
+    }
+
+    #[test]
+    fn decode_transfer_batch() {
+        // operator, from, to, ids_len, ids, values_len, values
+        let event = transfer_batch(&[1, 2, 3, 2, 10, 0, 11, 0, 2, 5, 0, 6, 0]).unwrap();
+
+        let items = super::decode_transfer_batch(&event).unwrap();
+        assert_eq!(
+            items,
+            vec![
+                (U256::from(10u128), U256::from(5u128)),
+                (U256::from(11u128), U256::from(6u128)),
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_transfer_single_combines_limbs() {
+        let definition: EventAbiEntry = serde_json::from_str(
+            r#"{
+                "type": "event",
+                "name": "TransferSingle",
+                "keys": [],
+                "data": [
+                    {"name": "operator", "type": "felt"},
+                    {"name": "from_", "type": "felt"},
+                    {"name": "to", "type": "felt"},
+                    {"name": "id", "type": "Uint256"},
+                    {"name": "value", "type": "Uint256"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        // operator, from, to, id, value
+        let data = [1, 2, 3, 7, 1, 4, 0]
+            .iter()
+            .map(|f| EventData(Felt::from_u64(*f)))
+            .collect::<Vec<_>>();
+        let event = AbiDecoder::with_structs([])
+            .decode(&definition, &[], &data)
+            .unwrap();
+
+        let (id, value) = super::decode_transfer_single(&event).unwrap();
+        assert_eq!(
+            id,
+            U256::from_limbs(Felt::from_u64(7), Felt::from_u64(1)).unwrap()
+        );
+        assert_eq!(id.high(), 1);
+        assert_eq!(value, U256::from(4u128));
+    }
+
+    #[test]
+    fn decode_transfer_batch_length_mismatch() {
+        let event = transfer_batch(&[1, 2, 3, 2, 10, 0, 11, 0, 1, 5, 0]).unwrap();
+
+        let error = super::decode_transfer_batch(&event).unwrap_err();
+        assert_eq!(
+            error,
+            EventDecodeError::ArrayLengthMismatch { ids: 2, values: 1 }
+        );
+    }
+
+    #[test]
+    fn decode_transfer_batch_truncated() {
+        let error = transfer_batch(&[1, 2, 3, 2, 10, 0, 11]).unwrap_err();
+        assert_eq!(
+            error,
+            DecodeError::MissingData {
+                expected: 8,
+                actual: 7
+            }
+        );
+    }
+}
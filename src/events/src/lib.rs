@@ -1,12 +1,23 @@
-mod class;
+mod cache;
+pub mod class;
+pub mod decoder;
+mod proxy;
+pub mod spec;
+use std::sync::Arc;
+
 use anyhow::Context;
-use class::{ContractAbiEntry, ContractClass};
+pub use cache::{AbiCache, ClassAbi};
+use class::decode::DecodeError;
+use class::ContractClass;
+pub use decoder::{DecoderRegistry, EventDecoder, IndexedEvent, RawEvent};
 use pathfinder_common::{
-    ContractAddress, EventData, EventKey, StarknetBlockNumber, StarknetTransactionHash,
+    ClassHash, ContractAddress, EventData, EventKey, StarknetBlockHash, StarknetBlockNumber,
+    StarknetBlockTimestamp, StarknetTransactionHash, StarknetTransactionIndex,
 };
-use pathfinder_serde::starkhash_to_dec_str;
+use proxy::Proxies;
+use rusqlite::types::ValueRef;
 use rusqlite::Transaction;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use stark_hash::Felt;
 #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
 pub enum EventType {
@@ -14,10 +25,13 @@ pub enum EventType {
     Mint,
     Burn,
     Transfer,
+    Approval,
+    ApprovalForAll,
 }
 #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
 
 pub enum ContractType {
+    ERC20,
     ERC721,
     ERC1155,
 }
@@ -25,45 +39,245 @@ pub enum ContractType {
 pub struct StarknetEventFilter {
     pub from_block: Option<StarknetBlockNumber>,
     pub to_block: Option<StarknetBlockNumber>,
-    pub keys: Vec<EventKey>,
+    /// Only return events emitted by this contract.
+    pub contract_address: Option<ContractAddress>,
+    /// Alternatives for each key position, an event matches if every position holds one of its
+    /// alternatives. An empty list of alternatives matches any key at that position.
+    pub keys: Vec<Vec<EventKey>>,
+    /// Maximum number of matching events read for one page, `None` reads all of them.
+    ///
+    /// The limit applies to the raw events, a page can hold more decoded events when a
+    /// `TransferBatch` is fanned out, or fewer when events do not match a known ABI.
+    pub page_size: Option<usize>,
+    /// Position to resume from, as returned with the previous page.
+    pub continuation_token: Option<ContinuationToken>,
+    /// What to do with events which cannot be decoded.
+    pub error_policy: ErrorPolicy,
+}
+
+/// Position of the first event of the next page.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContinuationToken {
+    pub block_number: StarknetBlockNumber,
+    pub transaction_index: StarknetTransactionIndex,
+    pub event_index: u64,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
 pub struct StarknetEmittedEvent {
     pub contract_address: ContractAddress,
+    /// Holder granting an approval.
+    owner: Option<String>,
+    /// Operator of a transfer, or the approved account or spender of an approval.
+    operator: Option<String>,
+    /// Whether an `ApprovalForAll` grants or revokes the approval.
+    approved: Option<bool>,
     from: String,
     to: String,
     token_id: String,
+    amount: String,
+    /// Position of the item within a `TransferBatch` event.
+    batch_index: Option<u64>,
     pub block_number: u64,
+    pub block_hash: StarknetBlockHash,
+    pub block_timestamp: StarknetBlockTimestamp,
     pub transaction_hash: StarknetTransactionHash,
+    /// Position of the transaction within its block.
+    pub transaction_index: StarknetTransactionIndex,
+    /// Position of the event within its transaction receipt.
+    pub event_index: u64,
     event_type: EventType,
     contrat_type: ContractType,
 }
 
+impl StarknetEmittedEvent {
+    /// An event emitted at `emitted` with all token fields left empty.
+    fn new(emitted: &EmittedAt, event_type: EventType, contract_type: ContractType) -> Self {
+        Self {
+            contract_address: emitted.contract_address,
+            owner: None,
+            operator: None,
+            approved: None,
+            from: String::new(),
+            to: String::new(),
+            token_id: String::new(),
+            amount: String::new(),
+            batch_index: None,
+            block_number: emitted.block_number,
+            block_hash: emitted.block_hash,
+            block_timestamp: emitted.block_timestamp,
+            transaction_hash: emitted.transaction_hash,
+            transaction_index: emitted.transaction_index,
+            event_index: emitted.event_index,
+            event_type,
+            contrat_type: contract_type,
+        }
+    }
+
+    /// An identifier which is unique for every decoded event and stable across re-runs.
+    pub fn id(&self) -> String {
+        let mut id = format!(
+            "{}-{}-{}",
+            self.block_number,
+            self.transaction_index.get(),
+            self.event_index
+        );
+        if let Some(batch_index) = self.batch_index {
+            id.push_str(&format!("-{batch_index}"));
+        }
+        id
+    }
+}
+
 #[derive(Copy, Clone, Debug, thiserror::Error, PartialEq, Eq)]
 pub enum EventFilterError {
     #[error("requested page size is too big, supported maximum is {0}")]
     PageSizeTooBig(usize),
+    #[error("requested page size must be greater than zero")]
+    PageSizeTooSmall,
+}
+
+#[derive(Copy, Clone, Debug, thiserror::Error, PartialEq, Eq)]
+pub enum EventDecodeError {
+    #[error("event has {actual} members, expected at least {expected}")]
+    MissingMember { expected: usize, actual: usize },
+    #[error("member {index} is not {expected}")]
+    UnexpectedType {
+        index: usize,
+        expected: &'static str,
+    },
+    #[error("ids and values arrays differ in length: {ids} != {values}")]
+    ArrayLengthMismatch { ids: usize, values: usize },
+}
+
+/// Failure to decode a single event, handled according to the filter's [ErrorPolicy].
+#[derive(Debug, thiserror::Error)]
+pub enum GetEventsError {
+    #[error("definition of class {0} is not a blob")]
+    InvalidDefinition(ClassHash),
+    #[error("decompressing definition of class {class_hash}")]
+    Decompress {
+        class_hash: ClassHash,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("parsing definition of class {class_hash}")]
+    ParseClass {
+        class_hash: ClassHash,
+        #[source]
+        source: anyhow::Error,
+    },
+    #[error("class {0} has no ABI")]
+    MissingAbi(ClassHash),
+    #[error("event data is not a sequence of felts")]
+    InvalidData,
+    #[error("event keys are not a sequence of base64 encoded felts")]
+    InvalidKeys(#[source] anyhow::Error),
+    #[error("event {event} has no member {path}")]
+    MissingMember { event: String, path: String },
+    #[error(transparent)]
+    Decode(#[from] EventDecodeError),
+    #[error(transparent)]
+    AbiDecode(#[from] DecodeError),
+    /// Failure of a downstream [EventDecoder](decoder::EventDecoder).
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+    /// Reading the class definition failed, this always stops the scan.
+    #[error("reading class definition")]
+    Database(#[from] rusqlite::Error),
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// What to do with an event which cannot be decoded.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Stop and return the error.
+    #[default]
+    Fail,
+    /// Leave the event out.
+    Skip,
+    /// Leave the event out and report it as a [SkippedEvent].
+    Collect,
+}
+
+/// An event left out under [ErrorPolicy::Collect].
+#[derive(Debug)]
+pub struct SkippedEvent {
+    pub contract_address: ContractAddress,
+    pub transaction_hash: StarknetTransactionHash,
+    /// Position of the event within its transaction receipt.
+    pub event_index: u64,
+    pub error: GetEventsError,
+}
+
+#[derive(Debug)]
 pub struct Events {
-    pub events: Vec<StarknetEmittedEvent>,
+    pub events: Vec<IndexedEvent>,
+    /// Set when the filter's page size was reached before all events were read.
+    pub continuation_token: Option<ContinuationToken>,
+    /// Events which could not be decoded, only filled under [ErrorPolicy::Collect].
+    pub skipped: Vec<SkippedEvent>,
+}
+
+/// Outcome of [StarknetEventsTable::for_each_event].
+#[derive(Debug)]
+pub struct Scan {
+    /// Set when the filter's page size was reached before all events were read.
+    pub continuation_token: Option<ContinuationToken>,
+    /// Events which could not be decoded, only filled under [ErrorPolicy::Collect].
+    pub skipped: Vec<SkippedEvent>,
+}
+
+/// Where an event was emitted, shared by all events decoded from it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EmittedAt {
+    pub contract_address: ContractAddress,
+    pub block_number: u64,
+    pub block_hash: StarknetBlockHash,
+    pub block_timestamp: StarknetBlockTimestamp,
+    pub transaction_hash: StarknetTransactionHash,
+    pub transaction_index: StarknetTransactionIndex,
+    pub event_index: u64,
 }
 
 pub struct StarknetEventsTable {}
 
 impl StarknetEventsTable {
+    pub const PAGE_SIZE_LIMIT: usize = 1024;
+
     fn encode_event_key_to_base64(key: &EventKey, buf: &mut String) {
         base64::encode_config_buf(key.0.as_be_bytes(), base64::STANDARD, buf);
     }
 
+    /// Decodes the space separated base64 encoded keys stored in `starknet_events.keys`.
+    fn decode_event_keys(keys: &str) -> anyhow::Result<Vec<EventKey>> {
+        // Base64 encodes 32 bytes in 44 characters, longer keys are rejected before they are
+        // decoded into a buffer of their size.
+        const MAX_KEY_LEN: usize = 44;
+
+        keys.split(' ')
+            .filter(|key| !key.is_empty())
+            .map(|key| {
+                anyhow::ensure!(
+                    key.len() <= MAX_KEY_LEN,
+                    "Key is {} characters long, more than a felt",
+                    key.len()
+                );
+                let bytes =
+                    base64::decode_config(key, base64::STANDARD).context("Decoding base64")?;
+                let key = Felt::from_be_slice(&bytes).context("Parsing felt")?;
+                Ok(EventKey(key))
+            })
+            .collect()
+    }
+
     fn event_query<'query, 'arg>(
         base: &'query str,
         from_block: Option<&'arg StarknetBlockNumber>,
         to_block: Option<&'arg StarknetBlockNumber>,
-        keys: &'arg [EventKey],
+        contract_address: Option<&'arg ContractAddress>,
+        keys: &'arg [Vec<EventKey>],
         key_fts_expression: &'arg mut String,
+        continuation_token: Option<&'arg ContinuationToken>,
     ) -> (
         std::borrow::Cow<'query, str>,
         Vec<(&'static str, &'arg dyn rusqlite::ToSql)>,
@@ -91,22 +305,50 @@ impl StarknetEventsTable {
             (None, None) => {}
         }
 
-        if !keys.is_empty() {
-            let needed =
-                (keys.len() * (" OR ".len() + "\"\"".len() + 44)).saturating_sub(" OR ".len());
+        // on contract address
+        if let Some(contract_address) = contract_address {
+            where_statement_parts.push("from_address = :contract_address");
+            params.push((":contract_address", contract_address));
+        }
+
+        // The full text index does not know the position of the keys, it only narrows the events
+        // down to ones holding an alternative for every position. Positions are checked by
+        // `keys_match` once the keys are read.
+        let positions: Vec<&Vec<EventKey>> = keys
+            .iter()
+            .filter(|alternatives| !alternatives.is_empty())
+            .collect();
+        if !positions.is_empty() {
+            let needed = positions
+                .iter()
+                .map(|alternatives| {
+                    "()".len()
+                        + (alternatives.len() * (" OR ".len() + "\"\"".len() + 44))
+                            .saturating_sub(" OR ".len())
+                })
+                .sum::<usize>()
+                + (positions.len() - 1) * " AND ".len();
             if let Some(more) = needed.checked_sub(key_fts_expression.capacity()) {
                 key_fts_expression.reserve(more);
             }
 
             let _capacity = key_fts_expression.capacity();
 
-            keys.iter().enumerate().for_each(|(i, key)| {
-                key_fts_expression.push('"');
-                Self::encode_event_key_to_base64(key, key_fts_expression);
-                key_fts_expression.push('"');
+            positions.iter().enumerate().for_each(|(i, alternatives)| {
+                key_fts_expression.push('(');
+                alternatives.iter().enumerate().for_each(|(j, key)| {
+                    key_fts_expression.push('"');
+                    Self::encode_event_key_to_base64(key, key_fts_expression);
+                    key_fts_expression.push('"');
+
+                    if j != alternatives.len() - 1 {
+                        key_fts_expression.push_str(" OR ");
+                    }
+                });
+                key_fts_expression.push(')');
 
-                if i != keys.len() - 1 {
-                    key_fts_expression.push_str(" OR ");
+                if i != positions.len() - 1 {
+                    key_fts_expression.push_str(" AND ");
                 }
             });
 
@@ -121,6 +363,14 @@ impl StarknetEventsTable {
             params.push((":events_match", &*key_fts_expression));
         }
 
+        // resume from the position of a previous page
+        if let Some(token) = continuation_token {
+            where_statement_parts.push("(block_number, t.idx, starknet_events.idx) >= (:token_block, :token_transaction, :token_event)");
+            params.push((":token_block", &token.block_number));
+            params.push((":token_transaction", &token.transaction_index));
+            params.push((":token_event", &token.event_index));
+        }
+
         if !where_statement_parts.is_empty() {
             let needed = " WHERE ".len()
                 + where_statement_parts.len() * " AND ".len()
@@ -153,18 +403,106 @@ impl StarknetEventsTable {
         (base_query, params)
     }
 
+    /// Checks `keys` against the alternatives of each key position of the filter.
+    fn keys_match(filter_keys: &[Vec<EventKey>], keys: &[EventKey]) -> bool {
+        filter_keys.iter().enumerate().all(|(i, alternatives)| {
+            alternatives.is_empty() || keys.get(i).is_some_and(|key| alternatives.contains(key))
+        })
+    }
+
+    /// Loads the class definition of `class_hash` and parses its ABI.
+    ///
+    /// The definition holds the whole compressed program, so it is only read once per class
+    /// instead of being selected for every event.
+    fn load_class_abi(
+        tx: &Transaction<'_>,
+        class_hash: ClassHash,
+    ) -> Result<ClassAbi, GetEventsError> {
+        let mut statement =
+            tx.prepare_cached("SELECT definition FROM contract_code WHERE hash = ?")?;
+        let definition = statement.query_row([class_hash], |row| {
+            Ok(row
+                .get_ref_unwrap("definition")
+                .as_blob()
+                .map(zstd::decode_all))
+        })?;
+        let definition = definition
+            .map_err(|_| GetEventsError::InvalidDefinition(class_hash))?
+            .map_err(|source| GetEventsError::Decompress { class_hash, source })?;
+
+        let class = ContractClass::from_definition_bytes(&definition)
+            .map_err(|source| GetEventsError::ParseClass { class_hash, source })?;
+        let abi = class.abi.ok_or(GetEventsError::MissingAbi(class_hash))?;
+
+        Ok(ClassAbi::new(abi).with_warnings(class.warnings, class.unknown_abi_entries))
+    }
+
+    /// Returns the events matching `filter`, decoded by the first decoder in `decoders` which
+    /// handles them. Contract ABIs are looked up in and added to `abi_cache`.
+    ///
+    /// Events are ordered by block number, transaction index and event index, independent of
+    /// how the query is executed.
+    ///
+    /// All events are collected in memory, see [StarknetEventsTable::for_each_event] for
+    /// processing large block ranges.
     pub fn get_events(
         tx: &Transaction<'_>,
         filter: &StarknetEventFilter,
+        abi_cache: &AbiCache,
+        decoders: &DecoderRegistry,
     ) -> anyhow::Result<Events> {
+        let mut events = Vec::new();
+        let scan = Self::for_each_event(tx, filter, abi_cache, decoders, |event| {
+            events.push(event);
+            Ok(())
+        })?;
+
+        Ok(Events {
+            events,
+            continuation_token: scan.continuation_token,
+            skipped: scan.skipped,
+        })
+    }
+
+    /// Calls `f` with each decoded event matching `filter` as soon as its row is read, so that
+    /// memory use does not grow with the number of matches.
+    ///
+    /// Events are passed in the same order [StarknetEventsTable::get_events] returns them. An
+    /// error returned by `f` stops the scan and is returned as is.
+    ///
+    /// Returns the token of the next page if the filter's page size was reached, and the events
+    /// left out under [ErrorPolicy::Collect].
+    pub fn for_each_event(
+        tx: &Transaction<'_>,
+        filter: &StarknetEventFilter,
+        abi_cache: &AbiCache,
+        decoders: &DecoderRegistry,
+        mut f: impl FnMut(IndexedEvent) -> anyhow::Result<()>,
+    ) -> anyhow::Result<Scan> {
+        match filter.page_size {
+            Some(0) => return Err(EventFilterError::PageSizeTooSmall.into()),
+            Some(page_size) if page_size > Self::PAGE_SIZE_LIMIT => {
+                return Err(EventFilterError::PageSizeTooBig(Self::PAGE_SIZE_LIMIT).into())
+            }
+            _ => {}
+        }
+
+        // The class definition itself is loaded by `load_class_abi`, joining `contract_code` only
+        // skips contracts whose class is not known.
         let base_query = r#"SELECT
                   block_number,
+                  b.hash as block_hash,
+                  b.timestamp as block_timestamp,
+                  t.idx as transaction_idx,
+                  starknet_events.idx as event_idx,
                   transaction_hash,
                   from_address,
-                  cc.definition,
+                  c.hash as class_hash,
                   data,
                   starknet_events.keys as keys
                FROM starknet_events
+                INNER JOIN starknet_blocks as b ON (starknet_events.block_number = b.number)
+                INNER JOIN starknet_transactions as t ON (starknet_events.transaction_hash = t.hash)
                 INNER JOIN contracts as c ON (starknet_events.from_address = c.address) 
                 INNER JOIN contract_code as cc ON (c.hash = cc.hash) "#;
 
@@ -174,85 +512,1186 @@ impl StarknetEventsTable {
             base_query,
             filter.from_block.as_ref(),
             filter.to_block.as_ref(),
+            filter.contract_address.as_ref(),
             &filter.keys,
             &mut key_fts_expression,
+            filter.continuation_token.as_ref(),
         );
 
+        base_query
+            .to_mut()
+            .push_str(" ORDER BY block_number, transaction_idx, event_idx");
+
+        // We have to be able to decide if there are more events. We request one extra event
+        // above the requested page size, so that we can decide. Events failing the key position
+        // check do not count towards the page, so the query cannot be limited then.
+        let positional_keys = filter
+            .keys
+            .iter()
+            .any(|alternatives| !alternatives.is_empty());
+        let limit = filter
+            .page_size
+            .filter(|_| !positional_keys)
+            .map(|page_size| page_size + 1);
+        if let Some(limit) = &limit {
+            base_query.to_mut().push_str(" LIMIT :limit");
+            params.push((":limit", limit));
+        }
+
         let mut statement = tx.prepare(&base_query).context("Preparing SQL query")?;
         let mut rows = statement
             .query(params.as_slice())
             .context("Executing SQL query")?;
 
-        let mut emitted_events = Vec::new();
+        let mut read = 0;
+        let mut skipped = Vec::new();
+        let mut proxies = Proxies::default();
         while let Some(row) = rows.next().context("Fetching next event")? {
-            let abi = row.get_ref_unwrap("definition").as_blob().unwrap();
-            let abi = zstd::decode_all(&*abi).unwrap();
-            let class = ContractClass::from_definition_bytes(&abi);
-            let abi = class.ok().unwrap().abi.unwrap();
-            let block_number = row.get_ref_unwrap("block_number").as_i64().unwrap() as u64;
-            let transaction_hash: StarknetTransactionHash = row.get_unwrap("transaction_hash");
-
-            let contract_address: ContractAddress = row.get_unwrap("from_address");
-            let data = row.get_ref_unwrap("data").as_blob().unwrap();
-            let data: Vec<_> = data
-                .chunks_exact(32)
-                .map(|data| {
-                    let data = Felt::from_be_slice(data).unwrap();
-                    EventData(data)
-                })
-                .collect();
-            for x in abi.iter() {
-                match x {
-                    ContractAbiEntry::Event(value) => match value.name.as_str() {
-                        "Transfer" => {
-                            let parameter_name = value.data.clone().unwrap();
-                            let _name = parameter_name[2].name.as_str();
-                            match _name {
-                                "_tokenId" => {
-                                    let from = &data[0].0;
-                                    let to = &data[1].0;
-                                    let token_id = starkhash_to_dec_str(&data[2].0);
-                                    let mut event = StarknetEmittedEvent {
-                                        contract_address,
-                                        from: from.to_string(),
-                                        to: to.to_string(),
-                                        token_id,
-                                        block_number,
-                                        transaction_hash,
-                                        event_type: EventType::None,
-                                        contrat_type: ContractType::ERC721,
-                                    };
-                                    match starkhash_to_dec_str(from).as_str() {
-                                        "0" => {
-                                            event.event_type = EventType::Mint;
-                                        }
-                                        _ => {
-                                            match starkhash_to_dec_str(to).as_str() {
-                                                "0" => {
-                                                    event.event_type = EventType::Burn;
-                                                }
-                                                _ => {
-                                                    event.event_type = EventType::Transfer;
-                                                }
-                                            }
-                                        }
-                                    }
-                                    emitted_events.push(event);
-                                }
-                                _ => {}
-                            }
-                        }
-                        "TransferSingle" => (),
-                        "TransferBatch" => (),
-                        _ => (),
-                    },
-                    _ => (),
+            // Keys which cannot be decoded cannot be matched either, the event is left to the
+            // error policy instead.
+            let keys = row
+                .get_ref("keys")?
+                .as_str()
+                .map_err(anyhow::Error::from)
+                .and_then(Self::decode_event_keys)
+                .map_err(GetEventsError::InvalidKeys);
+            if matches!(&keys, Ok(keys) if !Self::keys_match(&filter.keys, keys)) {
+                continue;
+            }
+
+            let transaction_index: StarknetTransactionIndex = row.get("transaction_idx")?;
+            let event_index = row.get::<_, i64>("event_idx")? as u64;
+
+            if Some(read) == filter.page_size {
+                return Ok(Scan {
+                    continuation_token: Some(ContinuationToken {
+                        block_number: row.get("block_number")?,
+                        transaction_index,
+                        event_index,
+                    }),
+                    skipped,
+                });
+            }
+            read += 1;
+
+            let class_hash: ClassHash = row.get("class_hash")?;
+            let emitted = EmittedAt {
+                contract_address: row.get("from_address")?,
+                block_number: row.get::<_, i64>("block_number")? as u64,
+                block_hash: row.get("block_hash")?,
+                block_timestamp: row.get("block_timestamp")?,
+                transaction_hash: row.get("transaction_hash")?,
+                transaction_index,
+                event_index,
+            };
+
+            let decoded = keys.and_then(|keys| {
+                let abi =
+                    abi_cache.get_or_load(class_hash, || Self::load_class_abi(tx, class_hash))?;
+                let (class_hash, abi) = Self::event_abi(
+                    tx,
+                    abi_cache,
+                    &mut proxies,
+                    class_hash,
+                    abi,
+                    &keys,
+                    &emitted,
+                )?;
+                Self::decode_event(
+                    &abi,
+                    class_hash,
+                    &keys,
+                    row.get_ref("data")?,
+                    &emitted,
+                    decoders,
+                )
+            });
+            match decoded {
+                Ok(events) => {
+                    for event in events {
+                        f(event)?;
+                    }
+                }
+                Err(GetEventsError::Database(error)) => {
+                    return Err(error).context("Loading class definition")
                 }
+                Err(error) => match filter.error_policy {
+                    ErrorPolicy::Fail => {
+                        return Err(error).with_context(|| {
+                            format!(
+                                "Decoding event {} in transaction {}",
+                                event_index, emitted.transaction_hash
+                            )
+                        })
+                    }
+                    ErrorPolicy::Skip => {}
+                    ErrorPolicy::Collect => skipped.push(SkippedEvent {
+                        contract_address: emitted.contract_address,
+                        transaction_hash: emitted.transaction_hash,
+                        event_index,
+                        error,
+                    }),
+                },
             }
         }
 
-        Ok(Events {
-            events: emitted_events,
+        Ok(Scan {
+            continuation_token: None,
+            skipped,
+        })
+    }
+
+    /// Returns the class and ABI to decode an event with. Events of proxies which the proxy does
+    /// not declare itself are decoded with the ABI of its implementation at the time.
+    fn event_abi(
+        tx: &Transaction<'_>,
+        abi_cache: &AbiCache,
+        proxies: &mut Proxies,
+        class_hash: ClassHash,
+        abi: Arc<ClassAbi>,
+        keys: &[EventKey],
+        emitted: &EmittedAt,
+    ) -> Result<(ClassHash, Arc<ClassAbi>), GetEventsError> {
+        if !abi.is_proxy() || abi.event(keys).is_some() {
+            return Ok((class_hash, abi));
+        }
+
+        match proxies.implementation_at(tx, emitted)? {
+            Some(implementation) => {
+                let abi = abi_cache
+                    .get_or_load(implementation, || Self::load_class_abi(tx, implementation))?;
+                Ok((implementation, abi))
+            }
+            None => Ok((class_hash, abi)),
+        }
+    }
+
+    /// Decodes the raw `data` of an event emitted by a contract with the given ABI. Events which
+    /// are not declared by the ABI, or which no decoder handles, decode to nothing.
+    fn decode_event(
+        abi: &ClassAbi,
+        class_hash: ClassHash,
+        keys: &[EventKey],
+        data: ValueRef<'_>,
+        emitted: &EmittedAt,
+        decoders: &DecoderRegistry,
+    ) -> Result<Vec<IndexedEvent>, GetEventsError> {
+        let data = data.as_blob().map_err(|_| GetEventsError::InvalidData)?;
+        if data.len() % 32 != 0 {
+            return Err(GetEventsError::InvalidData);
+        }
+        let data = data
+            .chunks_exact(32)
+            .map(|data| Felt::from_be_slice(data).map(EventData))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| GetEventsError::InvalidData)?;
+
+        let (selector, definition) = match (keys.first(), abi.event(keys)) {
+            (Some(selector), Some(definition)) => (selector, definition),
+            _ => return Ok(Vec::new()),
+        };
+        let decoder = match decoders.find(selector, abi) {
+            Some(decoder) => decoder,
+            None => return Ok(Vec::new()),
+        };
+
+        decoder.decode(&RawEvent {
+            abi,
+            class_hash,
+            definition,
+            keys,
+            data: &data,
+            emitted,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pathfinder_common::{felt, EntryPoint};
+
+    const SCHEMA: &str = r#"
+        CREATE TABLE starknet_blocks (
+            number    INTEGER PRIMARY KEY,
+            hash      BLOB NOT NULL,
+            timestamp INTEGER NOT NULL
+        );
+        CREATE TABLE starknet_transactions (
+            hash        BLOB PRIMARY KEY NOT NULL,
+            idx         INTEGER NOT NULL,
+            block_hash  BLOB NOT NULL
+        );
+        CREATE TABLE contracts (
+            address BLOB PRIMARY KEY,
+            hash    BLOB NOT NULL
+        );
+        CREATE TABLE contract_code (
+            hash       BLOB PRIMARY KEY,
+            definition BLOB
+        );
+        CREATE TABLE starknet_events (
+            block_number     INTEGER NOT NULL,
+            idx              INTEGER NOT NULL,
+            transaction_hash BLOB NOT NULL,
+            from_address     BLOB NOT NULL,
+            keys             TEXT,
+            data             BLOB
+        );
+        CREATE VIRTUAL TABLE starknet_events_keys
+        USING fts5(
+            keys,
+            content='starknet_events',
+            content_rowid='rowid',
+            tokenize='ascii'
+        );
+        CREATE TRIGGER starknet_events_ai
+        AFTER INSERT ON starknet_events
+        BEGIN
+            INSERT INTO starknet_events_keys(rowid, keys) VALUES (new.rowid, new.keys);
+        END;
+    "#;
+
+    const ABI: &str = r#"{"abi": [
+        {
+            "type": "function",
+            "name": "ownerOf",
+            "inputs": [{"name": "tokenId", "type": "felt"}],
+            "outputs": [{"name": "owner", "type": "felt"}],
+            "stateMutability": "view"
+        },
+        {
+            "type": "event",
+            "name": "Transfer",
+            "keys": [],
+            "data": [
+                {"name": "from_", "type": "felt"},
+                {"name": "to", "type": "felt"},
+                {"name": "tokenId", "type": "felt"}
+            ]
+        }
+    ]}"#;
+
+    fn transfer_key() -> EventKey {
+        EventKey(EntryPoint::hashed(b"Transfer").0)
+    }
+
+    /// Creates a database holding Transfer events at the given `(block, transaction, event)`
+    /// positions, inserted in the given order.
+    fn setup(positions: &[(u64, u64, u64)]) -> rusqlite::Connection {
+        let connection = rusqlite::Connection::open_in_memory().unwrap();
+        connection.execute_batch(SCHEMA).unwrap();
+
+        let class_hash = ClassHash(Felt::from_u64(0xc1a55));
+        let contract_address = ContractAddress::new_or_panic(Felt::from_u64(0xc0de));
+        let definition = zstd::encode_all(ABI.as_bytes(), 0).unwrap();
+        connection
+            .execute(
+                "INSERT INTO contracts (address, hash) VALUES (?, ?)",
+                rusqlite::params![contract_address, class_hash],
+            )
+            .unwrap();
+        connection
+            .execute(
+                "INSERT INTO contract_code (hash, definition) VALUES (?, ?)",
+                rusqlite::params![class_hash, definition],
+            )
+            .unwrap();
+
+        let mut keys = String::new();
+        StarknetEventsTable::encode_event_key_to_base64(&transfer_key(), &mut keys);
+
+        for (block, transaction, event) in positions {
+            connection
+                .execute(
+                    "INSERT OR IGNORE INTO starknet_blocks (number, hash, timestamp) VALUES (?, ?, ?)",
+                    rusqlite::params![block, StarknetBlockHash(Felt::from_u64(*block)), block * 10],
+                )
+                .unwrap();
+
+            let transaction_hash =
+                StarknetTransactionHash(Felt::from_u64(block * 1000 + transaction));
+            connection
+                .execute(
+                    "INSERT OR IGNORE INTO starknet_transactions (hash, idx, block_hash) VALUES (?, ?, ?)",
+                    rusqlite::params![transaction_hash, transaction, StarknetBlockHash(Felt::from_u64(*block))],
+                )
+                .unwrap();
+
+            let data = [1, 2, *event]
+                .iter()
+                .flat_map(|felt| Felt::from_u64(*felt).to_be_bytes())
+                .collect::<Vec<_>>();
+            connection
+                .execute(
+                    "INSERT INTO starknet_events (block_number, idx, transaction_hash, from_address, keys, data) VALUES (?, ?, ?, ?, ?, ?)",
+                    rusqlite::params![block, event, transaction_hash, contract_address, keys, data],
+                )
+                .unwrap();
+        }
+
+        connection
+    }
+
+    fn filter(keys: Vec<Vec<EventKey>>, page_size: Option<usize>) -> StarknetEventFilter {
+        StarknetEventFilter {
+            from_block: None,
+            to_block: None,
+            contract_address: None,
+            keys,
+            page_size,
+            continuation_token: None,
+            error_policy: ErrorPolicy::Fail,
+        }
+    }
+
+    fn tokens(events: &[IndexedEvent]) -> Vec<&StarknetEmittedEvent> {
+        events
+            .iter()
+            .map(|event| match event {
+                IndexedEvent::Token(event) => event,
+                IndexedEvent::Custom { .. } => panic!("not a token event: {event:?}"),
+            })
+            .collect()
+    }
+
+    fn positions(events: &[IndexedEvent]) -> Vec<(u64, u64, u64)> {
+        tokens(events)
+            .into_iter()
+            .map(|event| {
+                (
+                    event.block_number,
+                    event.transaction_index.get(),
+                    event.event_index,
+                )
+            })
+            .collect()
+    }
+
+    mod ordering {
+        use super::*;
+
+        const INSERTED: [(u64, u64, u64); 6] = [
+            (2, 1, 0),
+            (1, 1, 1),
+            (2, 0, 0),
+            (1, 1, 0),
+            (1, 0, 0),
+            (2, 1, 1),
+        ];
+
+        const ORDERED: [(u64, u64, u64); 6] = [
+            (1, 0, 0),
+            (1, 1, 0),
+            (1, 1, 1),
+            (2, 0, 0),
+            (2, 1, 0),
+            (2, 1, 1),
+        ];
+
+        #[test]
+        fn without_keys() {
+            let mut connection = setup(&INSERTED);
+            let tx = connection.transaction().unwrap();
+
+            let events = StarknetEventsTable::get_events(
+                &tx,
+                &filter(vec![], None),
+                &AbiCache::default(),
+                &DecoderRegistry::default(),
+            )
+            .unwrap();
+
+            assert_eq!(positions(&events.events), ORDERED);
+
+            let last = *tokens(&events.events).last().unwrap();
+            assert_eq!(last.block_hash, StarknetBlockHash(Felt::from_u64(2)));
+            assert_eq!(
+                last.block_timestamp,
+                StarknetBlockTimestamp::new_or_panic(20)
+            );
+        }
+
+        #[test]
+        fn with_keys() {
+            let mut connection = setup(&INSERTED);
+            let tx = connection.transaction().unwrap();
+
+            let events = StarknetEventsTable::get_events(
+                &tx,
+                &filter(vec![vec![transfer_key()]], None),
+                &AbiCache::default(),
+                &DecoderRegistry::default(),
+            )
+            .unwrap();
+
+            assert_eq!(positions(&events.events), ORDERED);
+        }
+
+        #[test]
+        fn across_pages() {
+            let mut connection = setup(&INSERTED);
+            let tx = connection.transaction().unwrap();
+            let abi_cache = AbiCache::default();
+
+            for keys in [vec![], vec![vec![transfer_key()]]] {
+                let mut filter = filter(keys, Some(4));
+                let mut pages = Vec::new();
+                loop {
+                    let page = StarknetEventsTable::get_events(
+                        &tx,
+                        &filter,
+                        &abi_cache,
+                        &DecoderRegistry::default(),
+                    )
+                    .unwrap();
+                    pages.extend(positions(&page.events));
+
+                    match page.continuation_token {
+                        Some(token) => filter.continuation_token = Some(token),
+                        None => break,
+                    }
+                }
+
+                assert_eq!(pages, ORDERED);
+            }
+        }
+    }
+
+    #[test]
+    fn page_size_bounds() {
+        let mut connection = setup(&[(0, 0, 0)]);
+        let tx = connection.transaction().unwrap();
+
+        for (page_size, expected) in [
+            (0, EventFilterError::PageSizeTooSmall),
+            (
+                StarknetEventsTable::PAGE_SIZE_LIMIT + 1,
+                EventFilterError::PageSizeTooBig(StarknetEventsTable::PAGE_SIZE_LIMIT),
+            ),
+        ] {
+            let error = StarknetEventsTable::get_events(
+                &tx,
+                &filter(vec![], Some(page_size)),
+                &AbiCache::default(),
+                &DecoderRegistry::default(),
+            )
+            .unwrap_err();
+            assert_eq!(error.downcast_ref::<EventFilterError>(), Some(&expected));
+        }
+
+        let events = StarknetEventsTable::get_events(
+            &tx,
+            &filter(vec![], Some(StarknetEventsTable::PAGE_SIZE_LIMIT)),
+            &AbiCache::default(),
+            &DecoderRegistry::default(),
+        )
+        .unwrap();
+        assert_eq!(positions(&events.events), [(0, 0, 0)]);
+    }
+
+    #[test]
+    fn contract_address() {
+        let mut connection = setup(&[(0, 0, 0), (0, 0, 1), (0, 1, 0)]);
+        // Move the second event to another contract of the same class.
+        let other = ContractAddress::new_or_panic(Felt::from_u64(0xbeef));
+        connection
+            .execute(
+                "INSERT INTO contracts (address, hash) VALUES (?, ?)",
+                rusqlite::params![other, ClassHash(Felt::from_u64(0xc1a55))],
+            )
+            .unwrap();
+        connection
+            .execute(
+                "UPDATE starknet_events SET from_address = ? WHERE block_number = 0 AND idx = 1",
+                [other],
+            )
+            .unwrap();
+        let tx = connection.transaction().unwrap();
+
+        for (contract_address, expected) in [
+            (0xc0de, vec![(0, 0, 0), (0, 1, 0)]),
+            (0xbeef, vec![(0, 0, 1)]),
+        ] {
+            let mut filter = filter(vec![], None);
+            filter.contract_address = Some(ContractAddress::new_or_panic(Felt::from_u64(
+                contract_address,
+            )));
+            let events = StarknetEventsTable::get_events(
+                &tx,
+                &filter,
+                &AbiCache::default(),
+                &DecoderRegistry::default(),
+            )
+            .unwrap();
+
+            assert_eq!(positions(&events.events), expected);
+            for event in tokens(&events.events) {
+                assert_eq!(event.contract_address, filter.contract_address.unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn cairo_1_key_members() {
+        let mut connection = setup(&[(0, 0, 0)]);
+
+        let abi = r#"[
+            {
+                "type": "function",
+                "name": "owner_of",
+                "inputs": [{"name": "token_id", "type": "core::integer::u256"}],
+                "outputs": [{"type": "core::starknet::contract_address::ContractAddress"}],
+                "state_mutability": "view"
+            },
+            {
+                "type": "event",
+                "name": "openzeppelin::token::erc721::erc721::ERC721Component::Transfer",
+                "kind": "struct",
+                "members": [
+                    {"name": "from", "type": "core::starknet::contract_address::ContractAddress", "kind": "key"},
+                    {"name": "to", "type": "core::starknet::contract_address::ContractAddress", "kind": "key"},
+                    {"name": "token_id", "type": "core::integer::u256", "kind": "key"}
+                ]
+            }
+        ]"#;
+        let definition = serde_json::json!({"sierra_program": [], "abi": abi}).to_string();
+        let definition = zstd::encode_all(definition.as_bytes(), 0).unwrap();
+        connection
+            .execute("UPDATE contract_code SET definition = ?", [definition])
+            .unwrap();
+
+        // Transfer of token 7 from 0x1 to 0x2, with no data.
+        let mut keys = String::new();
+        for key in [
+            transfer_key().0,
+            felt!("0x1"),
+            felt!("0x2"),
+            felt!("0x7"),
+            Felt::ZERO,
+        ] {
+            StarknetEventsTable::encode_event_key_to_base64(&EventKey(key), &mut keys);
+            keys.push(' ');
+        }
+        connection
+            .execute(
+                "UPDATE starknet_events SET keys = ?, data = ?",
+                rusqlite::params![keys, Vec::<u8>::new()],
+            )
+            .unwrap();
+
+        let tx = connection.transaction().unwrap();
+        let events = StarknetEventsTable::get_events(
+            &tx,
+            &filter(vec![], None),
+            &AbiCache::default(),
+            &DecoderRegistry::default(),
+        )
+        .unwrap();
+
+        assert_eq!(events.events.len(), 1);
+        let event = tokens(&events.events)[0];
+        assert_eq!(event.from, felt!("0x1").to_string());
+        assert_eq!(event.to, felt!("0x2").to_string());
+        assert_eq!(event.token_id, "7");
+        assert_eq!(event.contrat_type, ContractType::ERC721);
+    }
+
+    #[test]
+    fn erc20_transfer() {
+        let mut connection = setup(&[(0, 0, 0)]);
+
+        // Shares the Transfer selector with ERC-721, the decoder is chosen by `decimals`.
+        let abi = r#"{"abi": [
+            {
+                "type": "function",
+                "name": "decimals",
+                "inputs": [],
+                "outputs": [{"name": "decimals", "type": "felt"}],
+                "stateMutability": "view"
+            },
+            {
+                "type": "event",
+                "name": "Transfer",
+                "keys": [],
+                "data": [
+                    {"name": "from_", "type": "felt"},
+                    {"name": "to", "type": "felt"},
+                    {"name": "value", "type": "Uint256"}
+                ]
+            }
+        ]}"#;
+        let definition = zstd::encode_all(abi.as_bytes(), 0).unwrap();
+        connection
+            .execute("UPDATE contract_code SET definition = ?", [definition])
+            .unwrap();
+
+        // Transfer of 2^128 + 5 from 0x1 to 0x2.
+        let data = [1, 2, 5, 1]
+            .iter()
+            .flat_map(|felt| Felt::from_u64(*felt).to_be_bytes())
+            .collect::<Vec<_>>();
+        connection
+            .execute("UPDATE starknet_events SET data = ?", [data])
+            .unwrap();
+
+        let tx = connection.transaction().unwrap();
+        let events = StarknetEventsTable::get_events(
+            &tx,
+            &filter(vec![], None),
+            &AbiCache::default(),
+            &DecoderRegistry::default(),
+        )
+        .unwrap();
+
+        assert_eq!(events.events.len(), 1);
+        let event = tokens(&events.events)[0];
+        assert_eq!(event.contrat_type, ContractType::ERC20);
+        assert_eq!(event.event_type, EventType::Transfer);
+        assert_eq!(event.from, felt!("0x1").to_string());
+        assert_eq!(event.to, felt!("0x2").to_string());
+        assert_eq!(event.amount, "340282366920938463463374607431768211461");
+        assert_eq!(event.token_id, "");
+    }
+
+    #[test]
+    fn nft_approvals() {
+        let mut connection = setup(&[(0, 0, 0), (0, 0, 1), (0, 0, 2)]);
+
+        let abi = r#"{"abi": [
+            {
+                "type": "function",
+                "name": "ownerOf",
+                "inputs": [{"name": "tokenId", "type": "Uint256"}],
+                "outputs": [{"name": "owner", "type": "felt"}],
+                "stateMutability": "view"
+            },
+            {
+                "type": "event",
+                "name": "Approval",
+                "keys": [],
+                "data": [
+                    {"name": "owner", "type": "felt"},
+                    {"name": "approved", "type": "felt"},
+                    {"name": "tokenId", "type": "Uint256"}
+                ]
+            },
+            {
+                "type": "event",
+                "name": "ApprovalForAll",
+                "keys": [],
+                "data": [
+                    {"name": "owner", "type": "felt"},
+                    {"name": "operator", "type": "felt"},
+                    {"name": "approved", "type": "felt"}
+                ]
+            }
+        ]}"#;
+        let definition = zstd::encode_all(abi.as_bytes(), 0).unwrap();
+        connection
+            .execute("UPDATE contract_code SET definition = ?", [definition])
+            .unwrap();
+
+        // Approval of 0x2 for token 7, then 0x3 is made operator and revoked again.
+        for (idx, name, data) in [
+            (0, &b"Approval"[..], &[1, 2, 7, 0][..]),
+            (1, b"ApprovalForAll", &[1, 3, 1]),
+            (2, b"ApprovalForAll", &[1, 3, 0]),
+        ] {
+            let mut keys = String::new();
+            StarknetEventsTable::encode_event_key_to_base64(
+                &EventKey(EntryPoint::hashed(name).0),
+                &mut keys,
+            );
+            let data = data
+                .iter()
+                .flat_map(|felt| Felt::from_u64(*felt).to_be_bytes())
+                .collect::<Vec<_>>();
+            connection
+                .execute(
+                    "UPDATE starknet_events SET keys = ?, data = ? WHERE idx = ?",
+                    rusqlite::params![keys, data, idx],
+                )
+                .unwrap();
+        }
+
+        let tx = connection.transaction().unwrap();
+        let events = StarknetEventsTable::get_events(
+            &tx,
+            &filter(vec![], None),
+            &AbiCache::default(),
+            &DecoderRegistry::default(),
+        )
+        .unwrap();
+
+        let events = tokens(&events.events);
+        assert_eq!(events.len(), 3);
+        let owner = Some(felt!("0x1").to_string());
+
+        assert_eq!(events[0].event_type, EventType::Approval);
+        assert_eq!(events[0].contrat_type, ContractType::ERC721);
+        assert_eq!(events[0].owner, owner);
+        assert_eq!(events[0].operator, Some(felt!("0x2").to_string()));
+        assert_eq!(events[0].token_id, "7");
+        assert_eq!(events[0].approved, None);
+
+        for (event, approved) in events[1..].iter().zip([true, false]) {
+            assert_eq!(event.event_type, EventType::ApprovalForAll);
+            assert_eq!(event.contrat_type, ContractType::ERC721);
+            assert_eq!(event.owner, owner);
+            assert_eq!(event.operator, Some(felt!("0x3").to_string()));
+            assert_eq!(event.approved, Some(approved));
+        }
+    }
+
+    #[test]
+    fn custom_decoder() {
+        struct TransferLog;
+
+        impl EventDecoder for TransferLog {
+            fn event_names(&self) -> Vec<&str> {
+                vec!["Transfer"]
+            }
+
+            fn applies_to(&self, _: &ClassAbi) -> bool {
+                true
+            }
+
+            fn decode(&self, event: &RawEvent<'_>) -> Result<Vec<IndexedEvent>, GetEventsError> {
+                Ok(vec![IndexedEvent::Custom {
+                    collection: "transfers".to_owned(),
+                    document: bson::doc! { "event_index": event.emitted.event_index as i64 },
+                }])
+            }
+        }
+
+        let mut connection = setup(&[(0, 0, 0), (0, 0, 1)]);
+        let tx = connection.transaction().unwrap();
+        let mut decoders = DecoderRegistry::empty();
+        decoders.register(TransferLog);
+
+        let events = StarknetEventsTable::get_events(
+            &tx,
+            &filter(vec![], None),
+            &AbiCache::default(),
+            &decoders,
+        )
+        .unwrap();
+
+        let custom = |event_index: u64| IndexedEvent::Custom {
+            collection: "transfers".to_owned(),
+            document: bson::doc! { "event_index": event_index as i64 },
+        };
+        assert_eq!(events.events, vec![custom(0), custom(1)]);
+    }
+
+    #[test]
+    fn spec() {
+        let mut connection = setup(&[(0, 0, 0), (0, 1, 1)]);
+        let tx = connection.transaction().unwrap();
+        let abi_cache = AbiCache::default();
+
+        let spec = spec::Spec::from_toml(
+            r#"
+                [[index]]
+                collection = "transfers"
+                event = "Transfer"
+                contracts = ["0xc0de"]
+
+                [index.fields]
+                token = "tokenId"
+            "#,
+        )
+        .unwrap();
+        let compiled = spec.compile(&tx, &abi_cache).unwrap();
+        assert_eq!(compiled.filters.len(), 1);
+        assert_eq!(
+            compiled.filters[0].contract_address,
+            Some(ContractAddress::new_or_panic(Felt::from_u64(0xc0de)))
+        );
+
+        let events = StarknetEventsTable::get_events(
+            &tx,
+            &compiled.filters[0],
+            &abi_cache,
+            &compiled.decoders,
+        )
+        .unwrap();
+        let documents = events
+            .events
+            .iter()
+            .map(|event| match event {
+                IndexedEvent::Custom {
+                    collection,
+                    document,
+                } if collection == "transfers" => (
+                    document.get_i64("event_index").unwrap(),
+                    document.get_str("token").unwrap().to_owned(),
+                ),
+                event => panic!("not a transfers document: {event:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            documents,
+            [
+                (0, Felt::from_u64(0).to_string()),
+                (1, Felt::from_u64(1).to_string()),
+            ]
+        );
+
+        let spec = spec::Spec::from_toml(
+            r#"
+                [[index]]
+                collection = "transfers"
+                event = "Transfer"
+                class_hashes = ["0xc1a55"]
+                fields = { owner = "owner" }
+            "#,
+        )
+        .unwrap();
+        let error = spec.compile(&tx, &abi_cache).err().unwrap();
+        assert!(
+            matches!(&error, spec::SpecError::UnknownMember { path, .. } if path == "owner"),
+            "{error:?}"
+        );
+    }
+
+    #[test]
+    fn spec_reserved_columns() {
+        let mut connection = setup(&[(0, 0, 0)]);
+        let abi = ABI.replace(r#""name": "to""#, r#""name": "block_number""#);
+        let definition = zstd::encode_all(abi.as_bytes(), 0).unwrap();
+        connection
+            .execute("UPDATE contract_code SET definition = ?", [definition])
+            .unwrap();
+        let tx = connection.transaction().unwrap();
+        let abi_cache = AbiCache::default();
+
+        for (fields, reserved) in [
+            (r#"fields = { event_index = "tokenId" }"#, "event_index"),
+            // All members are stored, including `block_number`.
+            ("", "block_number"),
+        ] {
+            let spec = spec::Spec::from_toml(&format!(
+                r#"
+                    [[index]]
+                    collection = "transfers"
+                    event = "Transfer"
+                    contracts = ["0xc0de"]
+                    {fields}
+                "#
+            ))
+            .unwrap();
+            let error = spec.compile(&tx, &abi_cache).err().unwrap();
+            assert!(
+                matches!(&error, spec::SpecError::ReservedColumn { column, .. } if column == reserved),
+                "{error:?}"
+            );
+        }
+    }
+
+    mod proxy {
+        use super::*;
+
+        const PROXY_ABI: &str = r#"{"abi": [
+            {
+                "type": "function",
+                "name": "__default__",
+                "inputs": [
+                    {"name": "selector", "type": "felt"},
+                    {"name": "calldata_size", "type": "felt"},
+                    {"name": "calldata", "type": "felt*"}
+                ],
+                "outputs": [
+                    {"name": "retdata_size", "type": "felt"},
+                    {"name": "retdata", "type": "felt*"}
+                ]
+            },
+            {
+                "type": "event",
+                "name": "Upgraded",
+                "keys": [],
+                "data": [{"name": "implementation", "type": "felt"}]
+            }
+        ]}"#;
+
+        /// Puts the contract behind a proxy which is upgraded to `implementation` between the
+        /// transfers of blocks 0 and 1.
+        fn setup_proxy(implementation: Felt) -> rusqlite::Connection {
+            let connection = setup(&[(0, 0, 0), (1, 0, 0)]);
+            let proxy_hash = ClassHash(Felt::from_u64(0x9a0c5));
+            connection
+                .execute(
+                    "INSERT INTO contract_code (hash, definition) VALUES (?, ?)",
+                    rusqlite::params![
+                        proxy_hash,
+                        zstd::encode_all(PROXY_ABI.as_bytes(), 0).unwrap()
+                    ],
+                )
+                .unwrap();
+            connection
+                .execute("UPDATE contracts SET hash = ?", [proxy_hash])
+                .unwrap();
+
+            let mut keys = String::new();
+            StarknetEventsTable::encode_event_key_to_base64(
+                &EventKey(EntryPoint::hashed(b"Upgraded").0),
+                &mut keys,
+            );
+            connection
+                .execute(
+                    "INSERT INTO starknet_events (block_number, idx, transaction_hash, from_address, keys, data) VALUES (0, 1, ?, ?, ?, ?)",
+                    rusqlite::params![
+                        StarknetTransactionHash(Felt::from_u64(0)),
+                        ContractAddress::new_or_panic(Felt::from_u64(0xc0de)),
+                        keys,
+                        implementation.to_be_bytes().to_vec()
+                    ],
+                )
+                .unwrap();
+
+            connection
+        }
+
+        #[test]
+        fn upgraded_to_class() {
+            let mut connection = setup_proxy(Felt::from_u64(0xc1a55));
+            let tx = connection.transaction().unwrap();
+            let events = StarknetEventsTable::get_events(
+                &tx,
+                &filter(vec![], None),
+                &AbiCache::default(),
+                &DecoderRegistry::default(),
+            )
+            .unwrap();
+
+            assert_eq!(positions(&events.events), [(1, 0, 0)]);
+        }
+
+        #[test]
+        fn upgraded_to_contract_without_class() {
+            let mut connection = setup_proxy(Felt::from_u64(0xdead));
+            connection
+                .execute(
+                    "INSERT INTO contracts (address, hash) VALUES (?, ?)",
+                    rusqlite::params![
+                        ContractAddress::new_or_panic(Felt::from_u64(0xdead)),
+                        ClassHash(Felt::from_u64(0xbeef))
+                    ],
+                )
+                .unwrap();
+            let tx = connection.transaction().unwrap();
+            let events = StarknetEventsTable::get_events(
+                &tx,
+                &filter(vec![], None),
+                &AbiCache::default(),
+                &DecoderRegistry::default(),
+            )
+            .unwrap();
+
+            // The implementation is unknown, so the transfers are not declared.
+            assert!(events.events.is_empty());
+            assert!(events.skipped.is_empty());
+        }
+    }
+
+    #[test]
+    fn error_policy() {
+        let mut connection = setup(&[(0, 0, 0), (0, 0, 1)]);
+        // Truncate the token id of the first event.
+        connection
+            .execute(
+                "UPDATE starknet_events SET data = substr(data, 1, 64) WHERE idx = 0",
+                [],
+            )
+            .unwrap();
+        let tx = connection.transaction().unwrap();
+        let mut filter = filter(vec![], None);
+
+        filter.error_policy = ErrorPolicy::Fail;
+        StarknetEventsTable::get_events(
+            &tx,
+            &filter,
+            &AbiCache::default(),
+            &DecoderRegistry::default(),
+        )
+        .unwrap_err();
+
+        filter.error_policy = ErrorPolicy::Skip;
+        let events = StarknetEventsTable::get_events(
+            &tx,
+            &filter,
+            &AbiCache::default(),
+            &DecoderRegistry::default(),
+        )
+        .unwrap();
+        assert_eq!(positions(&events.events), [(0, 0, 1)]);
+        assert!(events.skipped.is_empty());
+
+        filter.error_policy = ErrorPolicy::Collect;
+        let events = StarknetEventsTable::get_events(
+            &tx,
+            &filter,
+            &AbiCache::default(),
+            &DecoderRegistry::default(),
+        )
+        .unwrap();
+        assert_eq!(positions(&events.events), [(0, 0, 1)]);
+        assert_eq!(events.skipped.len(), 1);
+        let skipped = &events.skipped[0];
+        assert_eq!(
+            skipped.transaction_hash,
+            StarknetTransactionHash(Felt::from_u64(0))
+        );
+        assert_eq!(skipped.event_index, 0);
+        assert!(matches!(
+            skipped.error,
+            GetEventsError::AbiDecode(DecodeError::MissingData {
+                expected: 3,
+                actual: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn invalid_keys_follow_error_policy() {
+        let mut connection = setup(&[(0, 0, 0), (0, 0, 1), (0, 0, 2)]);
+        connection
+            .execute(
+                "UPDATE starknet_events SET keys = 'not base64!' WHERE idx = 1",
+                [],
+            )
+            .unwrap();
+        let tx = connection.transaction().unwrap();
+        let mut filter = filter(vec![], None);
+
+        filter.error_policy = ErrorPolicy::Skip;
+        let events = StarknetEventsTable::get_events(
+            &tx,
+            &filter,
+            &AbiCache::default(),
+            &DecoderRegistry::default(),
+        )
+        .unwrap();
+        assert_eq!(positions(&events.events), [(0, 0, 0), (0, 0, 2)]);
+        assert!(events.skipped.is_empty());
+
+        filter.error_policy = ErrorPolicy::Collect;
+        let events = StarknetEventsTable::get_events(
+            &tx,
+            &filter,
+            &AbiCache::default(),
+            &DecoderRegistry::default(),
+        )
+        .unwrap();
+        assert_eq!(positions(&events.events), [(0, 0, 0), (0, 0, 2)]);
+        assert_eq!(events.skipped.len(), 1);
+        assert_eq!(events.skipped[0].event_index, 1);
+        assert!(matches!(
+            events.skipped[0].error,
+            GetEventsError::InvalidKeys(_)
+        ));
+    }
+
+    #[test]
+    fn oversized_keys_follow_error_policy() {
+        let mut connection = setup(&[(0, 0, 0), (0, 0, 1), (0, 0, 2)]);
+        // 48 and 33 bytes, neither fits a felt.
+        for (idx, key) in [(0, "A".repeat(64)), (1, "A".repeat(44))] {
+            connection
+                .execute(
+                    "UPDATE starknet_events SET keys = ? WHERE idx = ?",
+                    rusqlite::params![key, idx],
+                )
+                .unwrap();
+        }
+        let tx = connection.transaction().unwrap();
+        let mut filter = filter(vec![], None);
+        filter.error_policy = ErrorPolicy::Collect;
+
+        let events = StarknetEventsTable::get_events(
+            &tx,
+            &filter,
+            &AbiCache::default(),
+            &DecoderRegistry::default(),
+        )
+        .unwrap();
+        assert_eq!(positions(&events.events), [(0, 0, 2)]);
+        assert_eq!(events.skipped.len(), 2);
+        for skipped in &events.skipped {
+            assert!(matches!(skipped.error, GetEventsError::InvalidKeys(_)));
+        }
+    }
+
+    #[test]
+    fn abi_warnings() {
+        let mut connection = setup(&[(0, 0, 0)]);
+        let abi = r#"{"abi": [
+            {
+                "type": "function",
+                "name": "ownerOf",
+                "inputs": [{"name": "tokenId", "type": "felt"}],
+                "outputs": [{"name": "owner", "type": "felt"}],
+                "stateMutability": "view"
+            },
+            {
+                "type": "event",
+                "name": "Transfer",
+                "keys": [],
+                "data": [
+                    {"name": "from_", "type": "felt"},
+                    {"name": "to", "type": "felt"},
+                    {"name": "tokenId", "type": "felt"}
+                ]
+            },
+            {"type": "interface", "name": "IERC721"}
+        ]}"#;
+        let definition = zstd::encode_all(abi.as_bytes(), 0).unwrap();
+        connection
+            .execute("UPDATE contract_code SET definition = ?", [definition])
+            .unwrap();
+        let tx = connection.transaction().unwrap();
+
+        let abi_cache = AbiCache::default();
+        let events = StarknetEventsTable::get_events(
+            &tx,
+            &filter(vec![], None),
+            &abi_cache,
+            &DecoderRegistry::default(),
+        )
+        .unwrap();
+        assert_eq!(positions(&events.events), [(0, 0, 0)]);
+
+        let warnings = abi_cache.warnings();
+        assert_eq!(warnings.len(), 1);
+        let (class_hash, warnings) = &warnings[0];
+        assert_eq!(*class_hash, ClassHash(Felt::from_u64(0xc1a55)));
+        assert!(matches!(
+            &warnings[..],
+            [class::AbiWarning::UnknownEntry { index: 2, .. }]
+        ));
+    }
+
+    #[test]
+    fn keys_match_by_position() {
+        let transfer = EventKey(Felt::from_u64(1));
+        let owner = EventKey(Felt::from_u64(2));
+        let other = EventKey(Felt::from_u64(3));
+
+        let filter = vec![vec![transfer], vec![], vec![owner, other]];
+
+        assert!(StarknetEventsTable::keys_match(
+            &filter,
+            &[transfer, other, owner]
+        ));
+        assert!(!StarknetEventsTable::keys_match(
+            &filter,
+            &[owner, transfer, owner]
+        ));
+        assert!(!StarknetEventsTable::keys_match(
+            &filter,
+            &[transfer, owner]
+        ));
+        assert!(StarknetEventsTable::keys_match(&[], &[]));
+    }
+}
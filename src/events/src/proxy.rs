@@ -0,0 +1,169 @@
+//! Resolution of the implementation behind proxy contracts, whose own ABI only declares a
+//! `__default__` entry point and an `Upgraded` event.
+//!
+//! Proxies keep their implementation in a storage slot, but contract storage is not part of the
+//! database, so implementations are resolved from the history of `Upgraded` events instead.
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use anyhow::Context;
+use pathfinder_common::{ClassHash, ContractAddress, EntryPoint, EventKey};
+use rusqlite::{OptionalExtension, Transaction};
+use stark_hash::Felt;
+
+use crate::{EmittedAt, GetEventsError, StarknetEventsTable};
+
+pub(crate) const UPGRADED: &str = "Upgraded";
+
+/// Position of an event as block number, transaction index and event index.
+type Position = (u64, u64, u64);
+
+/// An `Upgraded` event, the implementation is `None` if it names neither a stored class nor a
+/// contract whose class is stored.
+struct Upgrade {
+    position: Position,
+    implementation: Option<ClassHash>,
+}
+
+/// The implementations of the proxies of a scan, each history is read once.
+#[derive(Default)]
+pub(crate) struct Proxies {
+    upgrades: HashMap<ContractAddress, Vec<Upgrade>>,
+}
+
+impl Proxies {
+    /// Returns the implementation of the proxy emitting the event at `emitted`, at the time it
+    /// was emitted.
+    pub(crate) fn implementation_at(
+        &mut self,
+        tx: &Transaction<'_>,
+        emitted: &EmittedAt,
+    ) -> Result<Option<ClassHash>, GetEventsError> {
+        let position = (
+            emitted.block_number,
+            emitted.transaction_index.get(),
+            emitted.event_index,
+        );
+        let upgrades = self.upgrades(tx, emitted.contract_address)?;
+
+        Ok(upgrades
+            .iter()
+            .take_while(|upgrade| upgrade.position < position)
+            .last()
+            .and_then(|upgrade| upgrade.implementation))
+    }
+
+    /// Returns the implementation `proxy` was last upgraded to.
+    pub(crate) fn latest_implementation(
+        &mut self,
+        tx: &Transaction<'_>,
+        proxy: ContractAddress,
+    ) -> Result<Option<ClassHash>, GetEventsError> {
+        let upgrades = self.upgrades(tx, proxy)?;
+
+        Ok(upgrades.last().and_then(|upgrade| upgrade.implementation))
+    }
+
+    fn upgrades(
+        &mut self,
+        tx: &Transaction<'_>,
+        proxy: ContractAddress,
+    ) -> Result<&[Upgrade], GetEventsError> {
+        let upgrades = match self.upgrades.entry(proxy) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(Self::load_upgrades(tx, proxy)?),
+        };
+
+        Ok(upgrades)
+    }
+
+    /// Reads the `Upgraded` events of `proxy` in the order they were emitted.
+    fn load_upgrades(
+        tx: &Transaction<'_>,
+        proxy: ContractAddress,
+    ) -> Result<Vec<Upgrade>, GetEventsError> {
+        let base_query = r#"SELECT
+                  block_number,
+                  t.idx as transaction_idx,
+                  starknet_events.idx as event_idx,
+                  data,
+                  starknet_events.keys as keys
+               FROM starknet_events
+                INNER JOIN starknet_transactions as t ON (starknet_events.transaction_hash = t.hash)"#;
+        let keys = vec![vec![EventKey(EntryPoint::hashed(UPGRADED.as_bytes()).0)]];
+        let mut key_fts_expression = String::new();
+        let (mut query, params) = StarknetEventsTable::event_query(
+            base_query,
+            None,
+            None,
+            Some(&proxy),
+            &keys,
+            &mut key_fts_expression,
+            None,
+        );
+        query
+            .to_mut()
+            .push_str(" ORDER BY block_number, transaction_idx, event_idx");
+
+        let mut statement = tx.prepare(&query)?;
+        let mut rows = statement.query(params.as_slice())?;
+        let mut upgrades = Vec::new();
+        while let Some(row) = rows.next()? {
+            let event_keys: String = row.get("keys")?;
+            let event_keys = StarknetEventsTable::decode_event_keys(&event_keys)
+                .context("Decoding event keys")?;
+            if !StarknetEventsTable::keys_match(&keys, &event_keys) {
+                continue;
+            }
+
+            // Upgraded(implementation: felt)
+            let data = row
+                .get_ref_unwrap("data")
+                .as_blob()
+                .map_err(|_| GetEventsError::InvalidData)?;
+            let implementation = match data.get(..32) {
+                Some(implementation) => {
+                    Felt::from_be_slice(implementation).map_err(|_| GetEventsError::InvalidData)?
+                }
+                None => return Err(GetEventsError::InvalidData),
+            };
+
+            upgrades.push(Upgrade {
+                position: (
+                    row.get::<_, i64>("block_number")? as u64,
+                    row.get::<_, i64>("transaction_idx")? as u64,
+                    row.get::<_, i64>("event_idx")? as u64,
+                ),
+                implementation: Self::resolve(tx, implementation)?,
+            });
+        }
+
+        Ok(upgrades)
+    }
+
+    /// Older proxies are upgraded to the address of an implementation contract, newer ones to
+    /// the hash of an implementation class. Only classes whose definition is stored are
+    /// returned, as the ABI of any other cannot be loaded.
+    fn resolve(
+        tx: &Transaction<'_>,
+        implementation: Felt,
+    ) -> Result<Option<ClassHash>, GetEventsError> {
+        let class_hash = ClassHash(implementation);
+        let mut statement = tx.prepare_cached("SELECT 1 FROM contract_code WHERE hash = ?")?;
+        if statement.exists([class_hash])? {
+            return Ok(Some(class_hash));
+        }
+
+        let address = match ContractAddress::new(implementation) {
+            Some(address) => address,
+            None => return Ok(None),
+        };
+        let mut statement = tx.prepare_cached(
+            "SELECT c.hash FROM contracts as c INNER JOIN contract_code as cc ON (c.hash = cc.hash) WHERE c.address = ?",
+        )?;
+
+        Ok(statement
+            .query_row([address], |row| row.get(0))
+            .optional()?)
+    }
+}
@@ -0,0 +1,514 @@
+//! Declarative indexing specifications, storing the events of chosen contracts or classes as
+//! documents of a collection without writing a decoder.
+//!
+//! ```toml
+//! [[index]]
+//! collection = "listings"
+//! event = "Listed"
+//! contracts = ["0x4d7f..."]
+//! class_hashes = ["0x2a1c..."]
+//!
+//! [index.fields]
+//! seller = "listing.seller"
+//! price = "listing.price"
+//! ```
+//!
+//! Each field maps a column of the stored documents to the path of an event member, continued
+//! by the member names of a struct or the positions of a tuple. Without `fields` every member is
+//! stored under its own name. Documents also hold the position the event was emitted at.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+
+use bson::Bson;
+use pathfinder_common::{
+    ClassHash, ContractAddress, EntryPoint, EventKey, StarknetBlockTimestamp,
+    StarknetTransactionHash,
+};
+use pathfinder_serde::u256_to_dec_str;
+use rusqlite::{OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+
+use crate::class::cairo_type::{CairoType, TypeError};
+use crate::class::decode::{DecodedEvent, DecodedValue};
+use crate::class::{ContractAbiEntry, EventAbiEntry, StructAbiEntry};
+use crate::proxy::Proxies;
+use crate::{
+    AbiCache, ClassAbi, DecoderRegistry, EmittedAt, ErrorPolicy, EventDecoder, GetEventsError,
+    IndexedEvent, RawEvent, StarknetEventFilter, StarknetEventsTable,
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Spec {
+    #[serde(default, rename = "index")]
+    pub indexes: Vec<IndexSpec>,
+}
+
+/// Stores the events called `event` emitted by any of `contracts`, or by any contract of
+/// `class_hashes`, in `collection`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IndexSpec {
+    pub collection: String,
+    /// Name of the event as declared by the ABI, the name of its enum variant for Cairo 1 events.
+    pub event: String,
+    #[serde(default)]
+    pub contracts: Vec<ContractAddress>,
+    #[serde(default)]
+    pub class_hashes: Vec<ClassHash>,
+    /// Member paths by the column they are stored in.
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SpecError {
+    #[error("reading spec")]
+    Io(#[from] std::io::Error),
+    #[error("parsing spec")]
+    Parse(#[from] toml::de::Error),
+    #[error("index of {collection} has neither contracts nor class hashes")]
+    NoSources { collection: String },
+    #[error("contract {0} is not known")]
+    UnknownContract(ContractAddress),
+    #[error("loading ABI of class {class_hash}")]
+    Abi {
+        class_hash: ClassHash,
+        #[source]
+        source: GetEventsError,
+    },
+    #[error("resolving implementation of proxy {contract}")]
+    Proxy {
+        contract: ContractAddress,
+        #[source]
+        source: GetEventsError,
+    },
+    #[error("class {class_hash} does not declare event {event}")]
+    UnknownEvent {
+        class_hash: ClassHash,
+        event: String,
+    },
+    #[error("event {event} of class {class_hash} has no member {path}")]
+    UnknownMember {
+        class_hash: ClassHash,
+        event: String,
+        path: String,
+    },
+    #[error("column {column} of {collection} is reserved for the position of the event")]
+    ReservedColumn { collection: String, column: String },
+    #[error("event {event} of class {class_hash} has a member of invalid type")]
+    InvalidType {
+        class_hash: ClassHash,
+        event: String,
+        #[source]
+        source: TypeError,
+    },
+    #[error("reading contract classes")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// The filters and decoders of a [Spec].
+pub struct CompiledSpec {
+    /// The filters selecting the events of the spec, without a block range.
+    ///
+    /// Scanning each filter once indexes every event once, even if several indexes store it.
+    pub filters: Vec<StarknetEventFilter>,
+    pub decoders: DecoderRegistry,
+}
+
+impl Spec {
+    pub fn from_toml(s: &str) -> Result<Self, SpecError> {
+        Ok(toml::from_str(s)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SpecError> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Checks every index against the ABIs of its classes, read from `contract_code`, and
+    /// compiles the spec into one filter per event and contract, and one decoder per event.
+    pub fn compile(
+        &self,
+        tx: &Transaction<'_>,
+        abi_cache: &AbiCache,
+    ) -> Result<CompiledSpec, SpecError> {
+        let mut by_event = BTreeMap::<&str, Vec<IndexSpec>>::new();
+        for index in &self.indexes {
+            index.validate(tx, abi_cache)?;
+            by_event
+                .entry(index.event.as_str())
+                .or_default()
+                .push(index.clone());
+        }
+
+        let mut filters = Vec::new();
+        let mut decoders = DecoderRegistry::empty();
+        for (event, indexes) in by_event {
+            let selector = EventKey(EntryPoint::hashed(event.as_bytes()).0);
+            let filter = |contract_address| StarknetEventFilter {
+                from_block: None,
+                to_block: None,
+                contract_address,
+                keys: vec![vec![selector]],
+                page_size: None,
+                continuation_token: None,
+                error_policy: ErrorPolicy::default(),
+            };
+
+            // Events of a class can be emitted by any contract.
+            if indexes.iter().any(|index| !index.class_hashes.is_empty()) {
+                filters.push(filter(None));
+            } else {
+                let contracts = indexes
+                    .iter()
+                    .flat_map(|index| index.contracts.iter().copied())
+                    .collect::<BTreeSet<_>>();
+                filters.extend(contracts.into_iter().map(Some).map(filter));
+            }
+
+            decoders.register(SpecDecoder {
+                event: event.to_owned(),
+                indexes,
+            });
+        }
+
+        Ok(CompiledSpec { filters, decoders })
+    }
+}
+
+impl IndexSpec {
+    fn validate(&self, tx: &Transaction<'_>, abi_cache: &AbiCache) -> Result<(), SpecError> {
+        if self.contracts.is_empty() && self.class_hashes.is_empty() {
+            return Err(SpecError::NoSources {
+                collection: self.collection.clone(),
+            });
+        }
+
+        if let Some(column) = self.fields.keys().find(|column| is_reserved(column)) {
+            return Err(SpecError::ReservedColumn {
+                collection: self.collection.clone(),
+                column: column.clone(),
+            });
+        }
+
+        let load = |class_hash| {
+            abi_cache
+                .get_or_load(class_hash, || {
+                    StarknetEventsTable::load_class_abi(tx, class_hash)
+                })
+                .map_err(|source| SpecError::Abi { class_hash, source })
+        };
+
+        let mut class_hashes = self.class_hashes.clone();
+        let mut proxies = Proxies::default();
+        let mut statement = tx.prepare_cached("SELECT hash FROM contracts WHERE address = ?")?;
+        for contract in &self.contracts {
+            let mut class_hash = statement
+                .query_row([contract], |row| row.get(0))
+                .optional()?
+                .ok_or(SpecError::UnknownContract(*contract))?;
+
+            // Contracts behind a proxy are checked against their current implementation.
+            let abi = load(class_hash)?;
+            if abi.is_proxy() && abi.event_by_name(&self.event).is_none() {
+                let implementation =
+                    proxies
+                        .latest_implementation(tx, *contract)
+                        .map_err(|source| SpecError::Proxy {
+                            contract: *contract,
+                            source,
+                        })?;
+                class_hash = implementation.unwrap_or(class_hash);
+            }
+            class_hashes.push(class_hash);
+        }
+
+        for class_hash in class_hashes {
+            let abi = load(class_hash)?;
+            let definition =
+                abi.event_by_name(&self.event)
+                    .ok_or_else(|| SpecError::UnknownEvent {
+                        class_hash,
+                        event: self.event.clone(),
+                    })?;
+
+            // Without fields the members are stored under their own names.
+            if self.fields.is_empty() {
+                let member = definition
+                    .keys
+                    .iter()
+                    .chain(definition.data.iter())
+                    .flatten()
+                    .find(|member| is_reserved(&member.name));
+                if let Some(member) = member {
+                    return Err(SpecError::ReservedColumn {
+                        collection: self.collection.clone(),
+                        column: member.name.clone(),
+                    });
+                }
+            }
+
+            for path in self.fields.values() {
+                let found = member_exists(&abi, definition, path).map_err(|source| {
+                    SpecError::InvalidType {
+                        class_hash,
+                        event: self.event.clone(),
+                        source,
+                    }
+                })?;
+                if !found {
+                    return Err(SpecError::UnknownMember {
+                        class_hash,
+                        event: self.event.clone(),
+                        path: path.clone(),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn applies_to(&self, emitted: &EmittedAt, class_hash: ClassHash) -> bool {
+        self.contracts.contains(&emitted.contract_address)
+            || self.class_hashes.contains(&class_hash)
+    }
+
+    fn document(
+        &self,
+        decoded: &DecodedEvent,
+        emitted: &EmittedAt,
+    ) -> Result<bson::Document, GetEventsError> {
+        let mut columns = BTreeMap::new();
+        if self.fields.is_empty() {
+            for member in &decoded.members {
+                columns.insert(member.name.as_str(), to_bson(&member.value));
+            }
+        }
+        for (column, path) in &self.fields {
+            let value =
+                member_value(decoded, path).ok_or_else(|| GetEventsError::MissingMember {
+                    event: self.event.clone(),
+                    path: path.clone(),
+                })?;
+            columns.insert(column.as_str(), to_bson(value));
+        }
+
+        let document = Document {
+            contract_address: emitted.contract_address,
+            block_number: emitted.block_number,
+            block_timestamp: emitted.block_timestamp,
+            transaction_hash: emitted.transaction_hash,
+            event_index: emitted.event_index,
+            columns,
+        };
+        // Serialized straight into bson, as numbers passed through `serde_json::Value` keep
+        // their arbitrary precision representation.
+        bson::to_document(&document).map_err(|error| GetEventsError::Other(error.into()))
+    }
+}
+
+/// Whether `column` would overwrite a field of [Document] or the identifier MongoDB assigns.
+fn is_reserved(column: &str) -> bool {
+    matches!(
+        column,
+        "_id"
+            | "contract_address"
+            | "block_number"
+            | "block_timestamp"
+            | "transaction_hash"
+            | "event_index"
+    )
+}
+
+/// A stored event, the position it was emitted at followed by the columns of its index.
+#[derive(Serialize)]
+struct Document<'a> {
+    contract_address: ContractAddress,
+    block_number: u64,
+    block_timestamp: StarknetBlockTimestamp,
+    transaction_hash: StarknetTransactionHash,
+    event_index: u64,
+    #[serde(flatten)]
+    columns: BTreeMap<&'a str, Bson>,
+}
+
+/// Decodes the events of one name for all the indexes storing them.
+struct SpecDecoder {
+    event: String,
+    indexes: Vec<IndexSpec>,
+}
+
+impl EventDecoder for SpecDecoder {
+    fn event_names(&self) -> Vec<&str> {
+        vec![&self.event]
+    }
+
+    fn applies_to(&self, abi: &ClassAbi) -> bool {
+        abi.event_by_name(&self.event).is_some()
+    }
+
+    fn decode(&self, event: &RawEvent<'_>) -> Result<Vec<IndexedEvent>, GetEventsError> {
+        let indexes = self
+            .indexes
+            .iter()
+            .filter(|index| index.applies_to(event.emitted, event.class_hash))
+            .collect::<Vec<_>>();
+        if indexes.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let decoded = event.decode()?;
+        indexes
+            .into_iter()
+            .map(|index| {
+                Ok(IndexedEvent::Custom {
+                    collection: index.collection.clone(),
+                    document: index.document(&decoded, event.emitted)?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Whether `path` leads to a member of `event`, following the struct definitions of `abi`.
+fn member_exists(abi: &ClassAbi, event: &EventAbiEntry, path: &str) -> Result<bool, TypeError> {
+    let mut segments = path.split('.');
+    let first = segments.next().unwrap_or_default();
+    let member = event
+        .keys
+        .iter()
+        .chain(event.data.iter())
+        .flatten()
+        .find(|member| member.name == first);
+    let mut r#type = match member {
+        Some(member) => member.cairo_type()?,
+        None => return Ok(false),
+    };
+
+    let structs = abi
+        .abi
+        .iter()
+        .filter_map(|entry| match entry {
+            ContractAbiEntry::Struct(entry) => Some((entry.name.as_str(), entry)),
+            _ => None,
+        })
+        .collect::<HashMap<&str, &StructAbiEntry>>();
+
+    for segment in segments {
+        r#type = match r#type {
+            // A Uint256 is decoded as a whole, even if the ABI declares its struct.
+            CairoType::Named(name) if name == "Uint256" => return Ok(false),
+            CairoType::Named(name) => {
+                match structs
+                    .get(name.as_str())
+                    .and_then(|entry| entry.member_type(segment))
+                {
+                    Some(member_type) => CairoType::parse(member_type)?,
+                    None => return Ok(false),
+                }
+            }
+            CairoType::Tuple(mut members) => match segment.parse::<usize>() {
+                Ok(position) if position < members.len() => members.swap_remove(position).r#type,
+                _ => return Ok(false),
+            },
+            CairoType::Felt | CairoType::Pointer(_) => return Ok(false),
+        };
+    }
+
+    Ok(true)
+}
+
+/// Looks up the value at `path` in a decoded event, see [member_exists].
+fn member_value<'a>(decoded: &'a DecodedEvent, path: &str) -> Option<&'a DecodedValue> {
+    let mut segments = path.split('.');
+    let mut value = decoded.get(segments.next()?)?;
+    for segment in segments {
+        value = match value {
+            DecodedValue::Struct(members) => {
+                &members.iter().find(|member| member.name == segment)?.value
+            }
+            DecodedValue::Tuple(values) => values.get(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+
+    Some(value)
+}
+
+/// Felts are stored as hex strings and `Uint256`s as decimal strings.
+fn to_bson(value: &DecodedValue) -> Bson {
+    match value {
+        DecodedValue::Felt(felt) => Bson::String(felt.to_string()),
+        DecodedValue::Uint256(value) => Bson::String(u256_to_dec_str(value)),
+        DecodedValue::Array(values) | DecodedValue::Tuple(values) => {
+            Bson::Array(values.iter().map(to_bson).collect())
+        }
+        DecodedValue::Struct(members) => Bson::Document(
+            members
+                .iter()
+                .map(|member| (member.name.clone(), to_bson(&member.value)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pathfinder_common::{StarknetBlockHash, StarknetTransactionIndex};
+    use stark_hash::Felt;
+
+    use super::*;
+    use crate::class::decode::DecodedMember;
+
+    #[test]
+    fn document_round_trips_through_bson() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Position {
+            block_number: u64,
+            block_timestamp: u64,
+            event_index: u64,
+            token: String,
+        }
+
+        let spec = Spec::from_toml(
+            r#"
+                [[index]]
+                collection = "transfers"
+                event = "Transfer"
+                contracts = ["0xc0de"]
+                fields = { token = "tokenId" }
+            "#,
+        )
+        .unwrap();
+        let decoded = DecodedEvent {
+            name: "Transfer".to_owned(),
+            members: vec![DecodedMember {
+                name: "tokenId".to_owned(),
+                value: DecodedValue::Felt(Felt::from_u64(7)),
+            }],
+        };
+        let emitted = EmittedAt {
+            contract_address: ContractAddress::new_or_panic(Felt::from_u64(0xc0de)),
+            block_number: 5,
+            block_hash: StarknetBlockHash(Felt::from_u64(5)),
+            block_timestamp: StarknetBlockTimestamp::new_or_panic(50),
+            transaction_hash: StarknetTransactionHash(Felt::from_u64(1)),
+            transaction_index: StarknetTransactionIndex::new_or_panic(0),
+            event_index: 3,
+        };
+
+        let document = spec.indexes[0].document(&decoded, &emitted).unwrap();
+
+        assert_eq!(document.get("block_number"), Some(&Bson::Int64(5)));
+        assert_eq!(
+            bson::from_document::<Position>(document).unwrap(),
+            Position {
+                block_number: 5,
+                block_timestamp: 50,
+                event_index: 3,
+                token: Felt::from_u64(7).to_string(),
+            }
+        );
+    }
+}
@@ -2,7 +2,8 @@
 use rusqlite::*;
 use pathfinder_common::{felt, EventKey};
 use std::time::{Duration, Instant};
-use moso_events::{StarknetEventFilter, StarknetEmittedEvent, StarknetEventsTable};
+use moso_events::spec::Spec;
+use moso_events::{AbiCache, DecoderRegistry, ErrorPolicy, StarknetEventFilter, StarknetEmittedEvent, StarknetEventsTable};
 use serde::Deserialize;
 use pathfinder_database::{MosoDb};
 #[derive(Clone, serde::Deserialize, Debug, PartialEq, Eq)]
@@ -16,7 +17,7 @@ use pathfinder_common::StarknetBlockNumber;
 #[serde(deny_unknown_fields)]
 pub struct EventFilter {
     #[serde(default)]
-    pub keys: Vec<EventKey>,
+    pub keys: Vec<Vec<EventKey>>,
 }
 
 #[tokio::main]
@@ -30,7 +31,8 @@ async fn main() {
         let filter = &StarknetEventFilter {
             from_block: Some(StarknetBlockNumber::new_or_panic(BLOCK_NUMBER as u64)),
             to_block: Some(StarknetBlockNumber::new_or_panic(TO_BLOCK_NUMBER as u64)), 
-            keys: vec![EventKey(felt!(
+            contract_address: None,
+            keys: vec![vec![EventKey(felt!(
                 "0x99cd8bde557814842a3121e8ddfd433a539b8c9f14bf31ebf108d12e6196e9" // Transfer event key
             )),
             EventKey(felt!(
@@ -39,16 +41,45 @@ async fn main() {
             EventKey(felt!(
                 "0x2563683c757f3abe19c4b7237e2285d8993417ddffe0b54a19eb212ea574b08" // TransferBatch event key
             ))
-            ],                                                                    
+            ]],                                                                    
+            page_size: None,
+            continuation_token: None,
+            error_policy: ErrorPolicy::Collect,
         };
         let tx = db.transaction().unwrap();
+        let abi_cache = AbiCache::default();
+        let decoders = DecoderRegistry::default();
+        // Events declared by the indexing spec, checked against the ABIs of their classes before scanning.
+        let spec = match std::env::var("INDEX_SPEC") {
+            Ok(path) => Some(Spec::load(path).expect("reading index spec").compile(&tx, &abi_cache).expect("invalid index spec")),
+            Err(_) => None,
+        };
 
-        let events = StarknetEventsTable::get_events(&tx, filter).unwrap();
+        let events = StarknetEventsTable::get_events(&tx, filter, &abi_cache, &decoders).unwrap();
+        for skipped in &events.skipped {
+            println!(
+                "skipped event {} of contract {} in transaction {}: {}",
+                skipped.event_index, skipped.contract_address, skipped.transaction_hash, skipped.error
+            );
+        }
         let events = events.events;
         let db = MosoDb::init().await;
-        let events = MosoDb::insert_events(&db, events).await;
+        let events = MosoDb::insert_indexed_events(&db, events).await;
+        if let Some(spec) = spec {
+            for mut spec_filter in spec.filters {
+                spec_filter.from_block = filter.from_block;
+                spec_filter.to_block = filter.to_block;
+                spec_filter.error_policy = ErrorPolicy::Collect;
+                let spec_events = StarknetEventsTable::get_events(&tx, &spec_filter, &abi_cache, &spec.decoders).unwrap();
+                MosoDb::insert_indexed_events(&db, spec_events.events).await;
+            }
+        }
         let duration = start.elapsed();
         println!("res {:?}", events);
         println!("Time elapsed in getContract is: {:?}", duration);
+        println!("ABI cache hits {} misses {}", abi_cache.hits(), abi_cache.misses());
+        for (class_hash, warnings) in abi_cache.warnings() {
+            println!("ABI of class {} was only partly parsed: {:?}", class_hash, warnings);
+        }
     } 
 }
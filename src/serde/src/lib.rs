@@ -5,7 +5,7 @@ use num_bigint::BigUint;
 use pathfinder_common::{
     CallParam, ConstructorParam, EthereumAddress, EventData, EventKey, Fee, GasPrice,
     L1ToL2MessagePayloadElem, L2ToL1MessagePayloadElem, StarknetBlockNumber,
-    TransactionSignatureElem, TransactionVersion,
+    TransactionSignatureElem, TransactionVersion, U256,
 };
 use serde::de::Visitor;
 use serde_with::{serde_conv, DeserializeAs, SerializeAs};
@@ -322,6 +322,12 @@ fn starkhash_from_dec_str(s: &str) -> Result<Felt, anyhow::Error> {
     }
 }
 
+/// A helper conversion function which formats a [U256] as a decimal string.
+pub fn u256_to_dec_str(u: &U256) -> String {
+    let b = BigUint::from_bytes_be(u.as_be_bytes());
+    b.to_str_radix(10)
+}
+
 /// A convenience function which parses a hex string into a byte array.
 ///
 /// Supports both upper and lower case hex strings, as well as an